@@ -0,0 +1 @@
+ALTER TABLE miners DROP COLUMN payout_pubkey, DROP COLUMN pending_payout_pubkey, DROP COLUMN pending_payout_available_at
@@ -0,0 +1 @@
+ALTER TABLE miners ADD COLUMN payout_pubkey VARCHAR(44), ADD COLUMN pending_payout_pubkey VARCHAR(44), ADD COLUMN pending_payout_available_at TIMESTAMP NULL
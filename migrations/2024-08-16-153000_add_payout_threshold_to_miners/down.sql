@@ -0,0 +1 @@
+ALTER TABLE miners DROP COLUMN payout_threshold
@@ -0,0 +1 @@
+ALTER TABLE miners ADD COLUMN payout_threshold BIGINT UNSIGNED
@@ -0,0 +1,8 @@
+CREATE TABLE stakes (
+  id INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+  pool_id INT NOT NULL,
+  txn_id INT NOT NULL,
+  amount BIGINT UNSIGNED NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP NOT NULL
+)
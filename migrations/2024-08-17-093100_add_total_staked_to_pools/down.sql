@@ -0,0 +1 @@
+ALTER TABLE pools DROP COLUMN total_staked
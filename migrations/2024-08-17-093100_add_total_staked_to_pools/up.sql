@@ -0,0 +1 @@
+ALTER TABLE pools ADD COLUMN total_staked BIGINT UNSIGNED DEFAULT 0 NOT NULL
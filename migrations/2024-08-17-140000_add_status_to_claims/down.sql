@@ -0,0 +1 @@
+ALTER TABLE claims DROP COLUMN status, DROP COLUMN error
@@ -0,0 +1 @@
+ALTER TABLE claims ADD COLUMN status VARCHAR(12) DEFAULT 'confirmed' NOT NULL, ADD COLUMN error VARCHAR(255)
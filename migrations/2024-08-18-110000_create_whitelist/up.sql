@@ -0,0 +1,6 @@
+CREATE TABLE whitelist (
+  id INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+  pubkey VARCHAR(44) NOT NULL UNIQUE,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP NOT NULL
+)
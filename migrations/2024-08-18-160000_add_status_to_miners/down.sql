@@ -0,0 +1 @@
+ALTER TABLE miners DROP COLUMN banned, DROP COLUMN status_reason, DROP COLUMN rewards_frozen
@@ -0,0 +1 @@
+ALTER TABLE miners ADD COLUMN banned BOOL DEFAULT false NOT NULL, ADD COLUMN status_reason VARCHAR(255), ADD COLUMN rewards_frozen BOOL DEFAULT false NOT NULL
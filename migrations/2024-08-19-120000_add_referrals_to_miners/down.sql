@@ -0,0 +1 @@
+ALTER TABLE miners DROP COLUMN referral_code, DROP COLUMN referrer_id
@@ -0,0 +1 @@
+ALTER TABLE miners ADD COLUMN referral_code VARCHAR(16) UNIQUE, ADD COLUMN referrer_id INT
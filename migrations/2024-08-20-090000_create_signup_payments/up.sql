@@ -0,0 +1,8 @@
+CREATE TABLE signup_payments (
+  id INT NOT NULL AUTO_INCREMENT PRIMARY KEY,
+  miner_pubkey VARCHAR(44) NOT NULL,
+  signature VARCHAR(200) NOT NULL UNIQUE,
+  amount BIGINT UNSIGNED NOT NULL,
+  created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+  updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP NOT NULL
+)
@@ -0,0 +1 @@
+ALTER TABLE submissions ADD COLUMN worker VARCHAR(32) DEFAULT 'default' NOT NULL
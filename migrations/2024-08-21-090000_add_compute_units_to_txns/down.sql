@@ -0,0 +1 @@
+ALTER TABLE txns DROP COLUMN compute_units
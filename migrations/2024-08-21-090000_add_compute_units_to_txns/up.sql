@@ -0,0 +1 @@
+ALTER TABLE txns ADD COLUMN compute_units INT UNSIGNED NULL
@@ -0,0 +1 @@
+ALTER TABLE txns ADD COLUMN bus TINYINT UNSIGNED NULL
@@ -1,4 +1,4 @@
-use diesel::{sql_types::{BigInt, Binary, Bool, Integer, Nullable, Text, TinyInt, Unsigned}, MysqlConnection, RunQueryDsl};
+use diesel::{result::{DatabaseErrorKind, Error as DieselError}, sql_types::{BigInt, Binary, Bool, Integer, Nullable, Text, TinyInt, Unsigned}, MysqlConnection, RunQueryDsl};
 use deadpool_diesel::mysql::{Manager, Pool};
 use tracing::{error, info};
 
@@ -10,6 +10,7 @@ pub enum AppDatabaseError {
     FailedToUpdateEntity,
     EntityDoesNotExist,
     FailedToInsertNewEntity,
+    EntityAlreadyExists,
     InteractionFailed,
     QueryFailed,
 }
@@ -137,16 +138,21 @@ impl AppDatabase {
     pub async fn decrease_miner_reward(&self, miner_id: i32, rewards_to_decrease: u64) -> Result<(), AppDatabaseError> {
         if let Ok(db_conn) = self.connection_pool.get().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
-                diesel::sql_query("UPDATE rewards SET balance = balance - ? WHERE miner_id = ?")
+                diesel::sql_query("UPDATE rewards SET balance = balance - ? WHERE miner_id = ? AND balance >= ?")
                 .bind::<Unsigned<BigInt>, _>(rewards_to_decrease)
                 .bind::<Integer, _>(miner_id)
+                .bind::<Unsigned<BigInt>, _>(rewards_to_decrease)
                 .execute(conn)
             }).await;
 
-            if res.is_ok() {
-                return Ok(());
-            } else {
-                return Err(AppDatabaseError::FailedToUpdateEntity);
+            // no rows updated means the balance was too low
+            match res {
+                Ok(Ok(1)) => {
+                    return Ok(());
+                },
+                _ => {
+                    return Err(AppDatabaseError::FailedToUpdateEntity);
+                }
             }
         } else {
             return Err(AppDatabaseError::FailedToGetConnectionFromPool);
@@ -158,12 +164,13 @@ impl AppDatabase {
     pub async fn add_new_submission(&self, submission: models::InsertSubmission) -> Result<(), AppDatabaseError> {
         if let Ok(db_conn) = self.connection_pool.get().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
-                diesel::sql_query("INSERT INTO submissions (miner_id, challenge_id, digest, nonce, difficulty) VALUES (?, ?, ?, ?, ?)")
+                diesel::sql_query("INSERT INTO submissions (miner_id, challenge_id, digest, nonce, difficulty, worker) VALUES (?, ?, ?, ?, ?, ?)")
                 .bind::<Integer, _>(submission.miner_id)
                 .bind::<Integer, _>(submission.challenge_id)
                 .bind::<Nullable<Binary>, _>(submission.digest)
                 .bind::<Unsigned<BigInt>, _>(submission.nonce)
                 .bind::<TinyInt, _>(submission.difficulty)
+                .bind::<Text, _>(submission.worker)
                 .execute(conn)
             }).await;
             match res {
@@ -193,7 +200,7 @@ impl AppDatabase {
     pub async fn get_all_submission_for_challenge(&self, challenge: Vec<u8>) -> Result<Vec<Submission>, AppDatabaseError> {
         if let Ok(db_conn) = self.connection_pool.get().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
-                diesel::sql_query("SELECT s.id, s.miner_id, s.challenge_id, s.digest, s.nonce, s.difficulty FROM submissions s JOIN challenges c ON c.id = s.challenge_id WHERE c.challenge = ? ORDER BY s.difficulty DESC")
+                diesel::sql_query("SELECT s.id, s.miner_id, s.challenge_id, s.digest, s.nonce, s.difficulty, s.worker FROM submissions s JOIN challenges c ON c.id = s.challenge_id WHERE c.challenge = ? ORDER BY s.difficulty DESC")
                 .bind::<Binary, _>(challenge)
                 .get_results::<Submission>(conn)
             }).await;
@@ -241,6 +248,28 @@ impl AppDatabase {
         };
     }
     
+    pub async fn get_worker_stats(&self, miner_id: i32, since_secs: u64) -> Result<Vec<models::WorkerStats>, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT worker, COUNT(*) as submissions, MAX(difficulty) as best_difficulty, MAX(created_at) as last_submission_at FROM submissions WHERE miner_id = ? AND created_at >= DATE_SUB(CURRENT_TIMESTAMP, INTERVAL ? SECOND) GROUP BY worker ORDER BY worker")
+                .bind::<Integer, _>(miner_id)
+                .bind::<Unsigned<BigInt>, _>(since_secs)
+                .get_results::<models::WorkerStats>(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(stats)) => {
+                    return Ok(stats)
+                },
+                _ => {
+                    return Err(AppDatabaseError::QueryFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
     pub async fn get_submission_id_with_challenge_id(&self, challenge: Vec<u8>) -> Result<i32, AppDatabaseError> {
         if let Ok(db_conn) = self.connection_pool.get().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
@@ -328,7 +357,7 @@ impl AppDatabase {
     pub async fn get_pool_by_authority_pubkey(&self, pool_pubkey: String) -> Result<models::Pool, AppDatabaseError> {
         if let Ok(db_conn) = self.connection_pool.get().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
-                diesel::sql_query("SELECT id, proof_pubkey, authority_pubkey, total_rewards, claimed_rewards FROM pools WHERE pools.authority_pubkey = ?")
+                diesel::sql_query("SELECT id, proof_pubkey, authority_pubkey, total_rewards, claimed_rewards, total_staked FROM pools WHERE pools.authority_pubkey = ?")
                 .bind::<Text, _>(pool_pubkey)
                 .get_result::<models::Pool>(conn)
             }).await;
@@ -405,6 +434,85 @@ impl AppDatabase {
         };
     }
 
+    pub async fn decrease_pool_claimed(&self, pool_authority_pubkey: String, claimed_rewards: u64) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("UPDATE pools SET claimed_rewards = claimed_rewards - ? WHERE authority_pubkey = ?")
+                .bind::<Unsigned<BigInt>, _>(claimed_rewards)
+                .bind::<Text, _>(pool_authority_pubkey)
+                .execute(conn)
+            }).await;
+
+            if res.is_ok() {
+                return Ok(());
+            } else {
+                return Err(AppDatabaseError::FailedToUpdateEntity);
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn update_pool_staked(&self, pool_authority_pubkey: String, staked: u64) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("UPDATE pools SET total_staked = total_staked + ? WHERE authority_pubkey = ?")
+                .bind::<Unsigned<BigInt>, _>(staked)
+                .bind::<Text, _>(pool_authority_pubkey)
+                .execute(conn)
+            }).await;
+
+            if res.is_ok() {
+                return Ok(());
+            } else {
+                return Err(AppDatabaseError::FailedToUpdateEntity);
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_pool_outstanding_rewards(&self, pool_id: i32) -> Result<models::Reward, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT CAST(COALESCE(SUM(r.balance), 0) AS UNSIGNED) as balance FROM rewards r WHERE r.pool_id = ?")
+                .bind::<Integer, _>(pool_id)
+                .get_result::<models::Reward>(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(reward)) => {
+                    return Ok(reward)
+                },
+                _ => {
+                    return Err(AppDatabaseError::QueryFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn add_new_stake(&self, stake: models::InsertStake) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("INSERT INTO stakes (pool_id, txn_id, amount) VALUES (?, ?, ?)")
+                .bind::<Integer, _>(stake.pool_id)
+                .bind::<Integer, _>(stake.txn_id)
+                .bind::<Unsigned<BigInt>, _>(stake.amount)
+                .execute(conn)
+            }).await;
+
+            if res.is_ok() {
+                return Ok(());
+            } else {
+                return Err(AppDatabaseError::FailedToInsertNewEntity);
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
     pub async fn add_new_miner(&self, miner_pubkey: String, is_enabled: bool) -> Result<(), AppDatabaseError> {
         if let Ok(db_conn) = self.connection_pool.get().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
@@ -428,7 +536,7 @@ impl AppDatabase {
     pub async fn get_miner_by_pubkey_str(&self, miner_pubkey: String) -> Result<Miner, AppDatabaseError> {
         if let Ok(db_conn) = self.connection_pool.get().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
-                diesel::sql_query("SELECT id, pubkey, enabled FROM miners WHERE miners.pubkey = ?")
+                diesel::sql_query("SELECT id, pubkey, enabled, banned, rewards_frozen FROM miners WHERE miners.pubkey = ?")
                 .bind::<Text, _>(miner_pubkey)
                 .get_result::<Miner>(conn)
             }).await;
@@ -447,14 +555,288 @@ impl AppDatabase {
 
     }
 
+    pub async fn update_miner_status(&self, miner_id: i32, enabled: bool, banned: bool, rewards_frozen: bool, reason: Option<String>) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("UPDATE miners SET enabled = ?, banned = ?, rewards_frozen = ?, status_reason = ? WHERE id = ?")
+                .bind::<Bool, _>(enabled)
+                .bind::<Bool, _>(banned)
+                .bind::<Bool, _>(rewards_frozen)
+                .bind::<Nullable<Text>, _>(reason)
+                .bind::<Integer, _>(miner_id)
+                .execute(conn)
+            }).await;
+
+            if res.is_ok() {
+                return Ok(());
+            } else {
+                return Err(AppDatabaseError::FailedToUpdateEntity);
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_miner_referral(&self, miner_id: i32) -> Result<models::MinerReferral, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT id, referral_code, referrer_id FROM miners WHERE id = ?")
+                .bind::<Integer, _>(miner_id)
+                .get_result::<models::MinerReferral>(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(referral)) => {
+                    return Ok(referral)
+                },
+                _ => {
+                    return Err(AppDatabaseError::EntityDoesNotExist);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_miner_by_referral_code(&self, referral_code: String) -> Result<models::MinerReferral, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT id, referral_code, referrer_id FROM miners WHERE referral_code = ?")
+                .bind::<Text, _>(referral_code)
+                .get_result::<models::MinerReferral>(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(referral)) => {
+                    return Ok(referral)
+                },
+                _ => {
+                    return Err(AppDatabaseError::EntityDoesNotExist);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn update_miner_referral_code(&self, miner_id: i32, referral_code: String) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("UPDATE miners SET referral_code = ? WHERE id = ? AND referral_code IS NULL")
+                .bind::<Text, _>(referral_code)
+                .bind::<Integer, _>(miner_id)
+                .execute(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(1)) => {
+                    return Ok(());
+                },
+                _ => {
+                    return Err(AppDatabaseError::FailedToUpdateEntity);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn update_miner_referrer(&self, miner_id: i32, referrer_id: i32) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("UPDATE miners SET referrer_id = ? WHERE id = ? AND referrer_id IS NULL")
+                .bind::<Integer, _>(referrer_id)
+                .bind::<Integer, _>(miner_id)
+                .execute(conn)
+            }).await;
+
+            if res.is_ok() {
+                return Ok(());
+            } else {
+                return Err(AppDatabaseError::FailedToUpdateEntity);
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn add_new_referral_earning(&self, earning: models::InsertReferralEarning) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("INSERT INTO referral_earnings (referrer_id, referee_id, pool_id, challenge_id, amount) VALUES (?, ?, ?, ?, ?)")
+                .bind::<Integer, _>(earning.referrer_id)
+                .bind::<Integer, _>(earning.referee_id)
+                .bind::<Integer, _>(earning.pool_id)
+                .bind::<Integer, _>(earning.challenge_id)
+                .bind::<Unsigned<BigInt>, _>(earning.amount)
+                .execute(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(_)) => {
+                    return Ok(());
+                },
+                Ok(Err(e)) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::QueryFailed);
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_referral_stats(&self, miner_id: i32) -> Result<models::ReferralStats, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT (SELECT COUNT(*) FROM miners WHERE referrer_id = ?) as referee_count, CAST((SELECT COALESCE(SUM(amount), 0) FROM referral_earnings WHERE referrer_id = ?) AS UNSIGNED) as total_earned")
+                .bind::<Integer, _>(miner_id)
+                .bind::<Integer, _>(miner_id)
+                .get_result::<models::ReferralStats>(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(stats)) => {
+                    return Ok(stats)
+                },
+                _ => {
+                    return Err(AppDatabaseError::QueryFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_miner_payout_address(&self, miner_pubkey: String) -> Result<models::MinerPayoutAddress, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT id, payout_pubkey, pending_payout_pubkey, pending_payout_available_at FROM miners WHERE miners.pubkey = ?")
+                .bind::<Text, _>(miner_pubkey)
+                .get_result::<models::MinerPayoutAddress>(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(payout_address)) => {
+                    return Ok(payout_address)
+                },
+                _ => {
+                    return Err(AppDatabaseError::EntityDoesNotExist);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn update_miner_pending_payout_address(&self, miner_id: i32, pending_payout_pubkey: String, timelock_secs: u64) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("UPDATE miners SET pending_payout_pubkey = ?, pending_payout_available_at = DATE_ADD(CURRENT_TIMESTAMP, INTERVAL ? SECOND) WHERE id = ?")
+                .bind::<Text, _>(pending_payout_pubkey)
+                .bind::<Unsigned<BigInt>, _>(timelock_secs)
+                .bind::<Integer, _>(miner_id)
+                .execute(conn)
+            }).await;
+
+            if res.is_ok() {
+                return Ok(());
+            } else {
+                return Err(AppDatabaseError::FailedToUpdateEntity);
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    /// Promotes the pending payout address once its timelock has elapsed.
+    /// Fails with `FailedToUpdateEntity` if the change is still locked or doesn't match.
+    pub async fn confirm_miner_pending_payout_address(&self, miner_id: i32, pending_payout_pubkey: String) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("UPDATE miners SET payout_pubkey = pending_payout_pubkey, pending_payout_pubkey = NULL, pending_payout_available_at = NULL WHERE id = ? AND pending_payout_pubkey = ? AND pending_payout_available_at <= CURRENT_TIMESTAMP")
+                .bind::<Integer, _>(miner_id)
+                .bind::<Text, _>(pending_payout_pubkey)
+                .execute(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(1)) => {
+                    return Ok(());
+                },
+                _ => {
+                    return Err(AppDatabaseError::FailedToUpdateEntity);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn update_miner_payout_threshold(&self, miner_id: i32, payout_threshold: u64) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("UPDATE miners SET payout_threshold = ? WHERE id = ?")
+                .bind::<Unsigned<BigInt>, _>(payout_threshold)
+                .bind::<Integer, _>(miner_id)
+                .execute(conn)
+            }).await;
+
+            if res.is_ok() {
+                return Ok(());
+            } else {
+                return Err(AppDatabaseError::FailedToUpdateEntity);
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_miners_for_auto_payout(&self, pool_id: i32, default_threshold: u64, limit: u64) -> Result<Vec<models::MinerPayoutCandidate>, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT m.id as miner_id, m.pubkey, r.balance FROM miners m JOIN rewards r ON m.id = r.miner_id WHERE r.pool_id = ? AND m.enabled = true AND m.rewards_frozen = false AND r.balance > 0 AND r.balance >= COALESCE(m.payout_threshold, ?) ORDER BY r.balance DESC LIMIT ?")
+                .bind::<Integer, _>(pool_id)
+                .bind::<Unsigned<BigInt>, _>(default_threshold)
+                .bind::<Unsigned<BigInt>, _>(limit)
+                .get_results::<models::MinerPayoutCandidate>(conn)
+            }).await;
+
+            match res {
+                Ok(interaction) => {
+                    match interaction {
+                        Ok(query) => {
+                            return Ok(query);
+                        },
+                        Err(e) => {
+                            error!("{:?}", e);
+                            return Err(AppDatabaseError::QueryFailed);
+                        }
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
     pub async fn add_new_claim(&self, claim: models::InsertClaim) -> Result<i32, AppDatabaseError> {
         if let Ok(db_conn) = self.connection_pool.get().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
-                diesel::sql_query("INSERT INTO claims (miner_id, pool_id, txn_id, amount) VALUES (?, ?, ?, ?)")
+                diesel::sql_query("INSERT INTO claims (miner_id, pool_id, txn_id, amount, status) VALUES (?, ?, ?, ?, ?)")
                 .bind::<Integer, _>(claim.miner_id)
                 .bind::<Integer, _>(claim.pool_id)
                 .bind::<Integer, _>(claim.txn_id)
                 .bind::<Unsigned<BigInt>, _>(claim.amount)
+                .bind::<Text, _>(claim.status)
                 .execute(conn)
             }).await;
 
@@ -469,13 +851,139 @@ impl AppDatabase {
 
     }
 
+    pub async fn get_miner_claims(&self, miner_pubkey: String, limit: u64, offset: u64) -> Result<Vec<models::ClaimWithTxn>, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT c.id, c.amount, c.status, t.signature, t.priority_fee, c.created_at FROM claims c JOIN miners m ON m.id = c.miner_id JOIN txns t ON t.id = c.txn_id WHERE m.pubkey = ? ORDER BY c.id DESC LIMIT ? OFFSET ?")
+                .bind::<Text, _>(miner_pubkey)
+                .bind::<Unsigned<BigInt>, _>(limit)
+                .bind::<Unsigned<BigInt>, _>(offset)
+                .get_results::<models::ClaimWithTxn>(conn)
+            }).await;
+
+            match res {
+                Ok(interaction) => {
+                    match interaction {
+                        Ok(query) => {
+                            return Ok(query);
+                        },
+                        Err(e) => {
+                            error!("{:?}", e);
+                            return Err(AppDatabaseError::QueryFailed);
+                        }
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_claim_status(&self, claim_id: i32) -> Result<models::ClaimStatus, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT c.id, c.miner_id, c.amount, c.status, c.error, t.signature FROM claims c JOIN txns t ON t.id = c.txn_id WHERE c.id = ?")
+                .bind::<Integer, _>(claim_id)
+                .get_result::<models::ClaimStatus>(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(claim)) => {
+                    return Ok(claim)
+                },
+                _ => {
+                    return Err(AppDatabaseError::EntityDoesNotExist);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_claim_status_by_sig(&self, sig: String) -> Result<models::ClaimStatus, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT c.id, c.miner_id, c.amount, c.status, c.error, t.signature FROM claims c JOIN txns t ON t.id = c.txn_id WHERE t.signature = ?")
+                .bind::<Text, _>(sig)
+                .get_result::<models::ClaimStatus>(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(claim)) => {
+                    return Ok(claim)
+                },
+                _ => {
+                    return Err(AppDatabaseError::EntityDoesNotExist);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_pending_claims(&self, pool_id: i32) -> Result<Vec<models::ClaimStatus>, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT c.id, c.miner_id, c.amount, c.status, c.error, t.signature FROM claims c JOIN txns t ON t.id = c.txn_id WHERE c.pool_id = ? AND c.status = 'pending'")
+                .bind::<Integer, _>(pool_id)
+                .get_results::<models::ClaimStatus>(conn)
+            }).await;
+
+            match res {
+                Ok(interaction) => {
+                    match interaction {
+                        Ok(query) => {
+                            return Ok(query);
+                        },
+                        Err(e) => {
+                            error!("{:?}", e);
+                            return Err(AppDatabaseError::QueryFailed);
+                        }
+                    }
+                },
+                Err(e) => {
+                    error!("{:?}", e);
+                    return Err(AppDatabaseError::InteractionFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn update_claim_status(&self, claim_id: i32, status: String, error: Option<String>) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("UPDATE claims SET status = ?, error = ? WHERE id = ?")
+                .bind::<Text, _>(status)
+                .bind::<Nullable<Text>, _>(error)
+                .bind::<Integer, _>(claim_id)
+                .execute(conn)
+            }).await;
+
+            if res.is_ok() {
+                return Ok(());
+            } else {
+                return Err(AppDatabaseError::FailedToUpdateEntity);
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
     pub async fn add_new_txn(&self, txn: models::InsertTxn) -> Result<(), AppDatabaseError> {
         if let Ok(db_conn) = self.connection_pool.get().await {
             let res = db_conn.interact(move |conn: &mut MysqlConnection| {
-                diesel::sql_query("INSERT INTO txns (txn_type, signature, priority_fee) VALUES (?, ?, ?)")
+                diesel::sql_query("INSERT INTO txns (txn_type, signature, priority_fee, compute_units, bus) VALUES (?, ?, ?, ?, ?)")
                 .bind::<Text, _>(txn.txn_type)
                 .bind::<Text, _>(txn.signature)
                 .bind::<Unsigned<Integer>, _>(txn.priority_fee)
+                .bind::<Nullable<Unsigned<Integer>>, _>(txn.compute_units)
+                .bind::<Nullable<Unsigned<TinyInt>>, _>(txn.bus)
                 .execute(conn)
             }).await;
 
@@ -577,4 +1085,134 @@ impl AppDatabase {
         };
 
     }
+
+    pub async fn add_signup_payment(&self, payment: models::InsertSignupPayment) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("INSERT INTO signup_payments (miner_pubkey, signature, amount) VALUES (?, ?, ?)")
+                .bind::<Text, _>(payment.miner_pubkey)
+                .bind::<Text, _>(payment.signature)
+                .bind::<Unsigned<BigInt>, _>(payment.amount)
+                .execute(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(_)) => {
+                    return Ok(());
+                },
+                Ok(Err(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _))) => {
+                    return Err(AppDatabaseError::EntityAlreadyExists);
+                },
+                _ => {
+                    return Err(AppDatabaseError::FailedToInsertNewEntity);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn remove_signup_payment(&self, signature: String) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("DELETE FROM signup_payments WHERE signature = ?")
+                .bind::<Text, _>(signature)
+                .execute(conn)
+            }).await;
+
+            if res.is_ok() {
+                return Ok(());
+            } else {
+                return Err(AppDatabaseError::FailedToUpdateEntity);
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn add_whitelist_entry(&self, pubkey: String) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("INSERT IGNORE INTO whitelist (pubkey) VALUES (?)")
+                .bind::<Text, _>(pubkey)
+                .execute(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(_)) => {
+                    return Ok(());
+                },
+                _ => {
+                    return Err(AppDatabaseError::FailedToInsertNewEntity);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn remove_whitelist_entry(&self, pubkey: String) -> Result<(), AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("DELETE FROM whitelist WHERE pubkey = ?")
+                .bind::<Text, _>(pubkey)
+                .execute(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(0)) => {
+                    return Err(AppDatabaseError::EntityDoesNotExist);
+                },
+                Ok(Ok(_)) => {
+                    return Ok(());
+                },
+                _ => {
+                    return Err(AppDatabaseError::FailedToUpdateEntity);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn get_whitelist(&self) -> Result<Vec<models::WhitelistEntry>, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT id, pubkey FROM whitelist ORDER BY id")
+                .get_results::<models::WhitelistEntry>(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(entries)) => {
+                    return Ok(entries)
+                },
+                _ => {
+                    return Err(AppDatabaseError::QueryFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
+
+    pub async fn is_whitelisted(&self, pubkey: String) -> Result<bool, AppDatabaseError> {
+        if let Ok(db_conn) = self.connection_pool.get().await {
+            let res = db_conn.interact(move |conn: &mut MysqlConnection| {
+                diesel::sql_query("SELECT id, pubkey FROM whitelist WHERE pubkey = ?")
+                .bind::<Text, _>(pubkey)
+                .get_results::<models::WhitelistEntry>(conn)
+            }).await;
+
+            match res {
+                Ok(Ok(entries)) => {
+                    return Ok(!entries.is_empty())
+                },
+                _ => {
+                    return Err(AppDatabaseError::QueryFailed);
+                }
+            }
+        } else {
+            return Err(AppDatabaseError::FailedToGetConnectionFromPool);
+        };
+    }
 }
@@ -0,0 +1,110 @@
+use ore_api::{consts::BUS_COUNT, state::Bus};
+use rand::Rng;
+
+/// Picks the bus a mine transaction pays out from. Busses that failed to load are
+/// `Err` and are never picked.
+pub trait BusStrategy: Send {
+    /// Index of the bus to use, or None if no bus could be loaded.
+    fn select(&mut self, busses: &[Result<Bus, ()>]) -> Option<usize>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum BusStrategyKind {
+    /// The bus with the most rewards left.
+    Richest,
+    /// A random bus, weighted by the rewards each has left.
+    WeightedRandom,
+    /// The bus whose rewards drained the least since the last submission.
+    LeastContended,
+}
+
+impl BusStrategyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BusStrategyKind::Richest => "richest",
+            BusStrategyKind::WeightedRandom => "weighted-random",
+            BusStrategyKind::LeastContended => "least-contended",
+        }
+    }
+
+    pub fn build(&self) -> Box<dyn BusStrategy> {
+        match self {
+            BusStrategyKind::Richest => Box::new(Richest),
+            BusStrategyKind::WeightedRandom => Box::new(WeightedRandom),
+            BusStrategyKind::LeastContended => Box::new(LeastContended::default()),
+        }
+    }
+}
+
+fn loaded(busses: &[Result<Bus, ()>]) -> impl Iterator<Item = (usize, &Bus)> {
+    busses.iter().enumerate().filter_map(|(i, bus)| bus.as_ref().ok().map(|bus| (i, bus)))
+}
+
+pub struct Richest;
+
+impl BusStrategy for Richest {
+    fn select(&mut self, busses: &[Result<Bus, ()>]) -> Option<usize> {
+        loaded(busses).max_by_key(|(_, bus)| bus.rewards).map(|(i, _)| i)
+    }
+}
+
+pub struct WeightedRandom;
+
+impl BusStrategy for WeightedRandom {
+    fn select(&mut self, busses: &[Result<Bus, ()>]) -> Option<usize> {
+        let candidates: Vec<(usize, u64)> = loaded(busses).map(|(i, bus)| (i, bus.rewards)).collect();
+        if candidates.is_empty() {
+            return None;
+        }
+
+        let total = candidates.iter().fold(0u64, |total, (_, rewards)| total.saturating_add(*rewards));
+        let mut rng = rand::thread_rng();
+        // every bus is drained, any of them is as good as another
+        if total == 0 {
+            return Some(candidates[rng.gen_range(0..candidates.len())].0);
+        }
+
+        let mut target = rng.gen_range(0..total);
+        for (i, rewards) in &candidates {
+            if target < *rewards {
+                return Some(*i);
+            }
+            target -= rewards;
+        }
+        candidates.last().map(|(i, _)| *i)
+    }
+}
+
+/// Rewards drained from a bus between submissions are a sign of how many other miners
+/// are writing to it. Busses seen for the first time count as undrained.
+#[derive(Default)]
+pub struct LeastContended {
+    last_rewards: [Option<u64>; BUS_COUNT],
+}
+
+impl BusStrategy for LeastContended {
+    fn select(&mut self, busses: &[Result<Bus, ()>]) -> Option<usize> {
+        let mut best: Option<(usize, u64, u64)> = None;
+        for (i, bus) in loaded(busses) {
+            // rewards going up means the epoch reset, which drained nothing
+            let drained = self.last_rewards.get(i).copied().flatten()
+                .map_or(0, |last| last.saturating_sub(bus.rewards));
+            if let Some(last_rewards) = self.last_rewards.get_mut(i) {
+                *last_rewards = Some(bus.rewards);
+            }
+
+            // a drained bus can't pay out, skip it unless they all are
+            let is_better = match best {
+                None => true,
+                Some((_, best_drained, best_rewards)) => {
+                    (bus.rewards > 0, std::cmp::Reverse(drained), bus.rewards)
+                        > (best_rewards > 0, std::cmp::Reverse(best_drained), best_rewards)
+                }
+            };
+            if is_better {
+                best = Some((i, drained, bus.rewards));
+            }
+        }
+        best.map(|(i, _, _)| i)
+    }
+}
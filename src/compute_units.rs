@@ -0,0 +1,124 @@
+use std::{collections::HashMap, time::{Duration, Instant}};
+
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcSimulateTransactionConfig};
+use solana_sdk::{compute_budget::{self, ComputeBudgetInstruction}, instruction::{Instruction, InstructionError}, pubkey::Pubkey, transaction::{Transaction, TransactionError}};
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+// Most compute units a transaction can request
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+// Estimates are simulated again after this long in case usage went up
+const CACHE_TTL: Duration = Duration::from_secs(600);
+
+/// Program, first data byte and account count of each instruction. Transactions of the
+/// same shape use about the same compute, so one simulation covers all of them.
+type InstructionShape = Vec<(Pubkey, Option<u8>, usize)>;
+
+fn instruction_shape(ixs: &[Instruction]) -> InstructionShape {
+    ixs.iter()
+        .filter(|ix| ix.program_id != compute_budget::id())
+        .map(|ix| (ix.program_id, ix.data.first().copied(), ix.accounts.len()))
+        .collect()
+}
+
+fn transaction_shape(tx: &Transaction) -> InstructionShape {
+    tx.message.instructions.iter()
+        .map(|ix| (tx.message.account_keys[ix.program_id_index as usize], ix.data.first().copied(), ix.accounts.len()))
+        .filter(|(program_id, _, _)| *program_id != compute_budget::id())
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ComputeUnits {
+    /// Units consumed when the transaction was simulated.
+    pub consumed: u32,
+    /// `consumed` plus the safety margin, to request with `set_compute_unit_limit`.
+    pub limit: u32,
+}
+
+struct CachedUnits {
+    units: ComputeUnits,
+    // None once the estimate proved too low and has to be simulated again
+    sampled_at: Option<Instant>,
+}
+
+pub struct ComputeUnitEstimator {
+    // extra units requested on top of the simulated usage, in percent
+    margin_percent: u32,
+    cache: Mutex<HashMap<InstructionShape, CachedUnits>>,
+}
+
+impl ComputeUnitEstimator {
+    pub fn new(margin_percent: u32) -> Self {
+        ComputeUnitEstimator {
+            margin_percent,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Simulates a transaction made of `ixs` to find how many compute units it needs.
+    /// `ixs` shouldn't include compute budget instructions. Returns None if the
+    /// simulation fails, callers should fall back to a fixed limit.
+    pub async fn estimate(&self, rpc_client: &RpcClient, payer: &Pubkey, ixs: &[Instruction]) -> Option<ComputeUnits> {
+        let shape = instruction_shape(ixs);
+        let cached = self.cache.lock().await.get(&shape).map(|cached| (cached.units, cached.sampled_at));
+        if let Some((units, Some(sampled_at))) = cached {
+            if sampled_at.elapsed() < CACHE_TTL {
+                return Some(units);
+            }
+        }
+
+        let mut sim_ixs = vec![ComputeBudgetInstruction::set_compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT)];
+        sim_ixs.extend_from_slice(ixs);
+        let tx = Transaction::new_with_payer(&sim_ixs, Some(payer));
+        let config = RpcSimulateTransactionConfig {
+            sig_verify: false,
+            replace_recent_blockhash: true,
+            commitment: Some(rpc_client.commitment()),
+            ..Default::default()
+        };
+
+        let consumed = match rpc_client.simulate_transaction_with_config(&tx, config).await {
+            Ok(response) => {
+                if let Some(err) = response.value.err {
+                    error!("Compute unit simulation failed: {}", err);
+                    return None;
+                }
+                response.value.units_consumed?.min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+            },
+            Err(e) => {
+                error!("Failed to simulate transaction for compute units: {}", e);
+                return None;
+            }
+        };
+
+        // keep the most seen for this shape, a single cheap run shouldn't undersize later transactions
+        let consumed = cached.map_or(consumed, |(units, _)| units.consumed.max(consumed));
+        let limit = (consumed as u64 * (100 + self.margin_percent as u64) / 100).min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32;
+        let units = ComputeUnits {
+            consumed,
+            limit,
+        };
+        info!("Simulated {} compute units, requesting {}", consumed, limit);
+        self.cache.lock().await.insert(shape, CachedUnits {
+            units,
+            sampled_at: Some(Instant::now()),
+        });
+        Some(units)
+    }
+
+    /// Raises the estimate for `tx`'s shape if it failed by running out of compute units.
+    /// The limit it failed with becomes the least it's known to use, and the next estimate
+    /// simulates again on top of that.
+    pub async fn record_error(&self, tx: &Transaction, err: &TransactionError) {
+        if !matches!(err, TransactionError::InstructionError(_, InstructionError::ComputationalBudgetExceeded)) {
+            return;
+        }
+
+        if let Some(cached) = self.cache.lock().await.get_mut(&transaction_shape(tx)) {
+            error!("Transaction ran out of its {} compute unit limit, raising the estimate", cached.units.limit);
+            cached.units.consumed = cached.units.limit;
+            cached.sampled_at = None;
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Events the server pushes to connected miners.
+//!
+//! The encoding is picked per connection with the `protocol` query param of the
+//! websocket handshake:
+//!
+//! - `legacy` (default): the original messages. Jobs are binary frames, earnings and
+//!   claim updates are free-form text, and events without a legacy form aren't sent.
+//! - `json`: every event is a text frame holding a json object with a `type` field,
+//!   e.g. `{"type":"share_accepted","difficulty":14,"nonce":123}`.
+//! - `binary`: every event is a binary frame. The first byte is the event id below and the
+//!   rest is the bincode encoded event body. Jobs keep the legacy 57 byte layout.
+//!
+//! All amounts are in ORE base units.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WsProtocol {
+    Legacy,
+    Json,
+    Binary,
+}
+
+impl WsProtocol {
+    pub fn from_param(param: Option<&str>) -> Option<Self> {
+        match param {
+            None | Some("legacy") => Some(WsProtocol::Legacy),
+            Some("json") => Some(WsProtocol::Json),
+            Some("binary") => Some(WsProtocol::Binary),
+            _ => None,
+        }
+    }
+}
+
+/// A new nonce range to mine for the current challenge. Event id 0.
+#[derive(Clone, Debug, Serialize)]
+pub struct JobEvent {
+    pub challenge: [u8; 32],
+    /// Seconds until the pool submits its best solution.
+    pub cutoff: i64,
+    pub nonce_start: u64,
+    pub nonce_end: u64,
+}
+
+/// A submitted solution was recorded for the current challenge. Event id 1.
+#[derive(Clone, Debug, Serialize)]
+pub struct ShareAcceptedEvent {
+    pub difficulty: u32,
+    pub nonce: u64,
+}
+
+/// A submitted solution was not counted. Event id 2.
+#[derive(Clone, Debug, Serialize)]
+pub struct ShareRejectedEvent {
+    pub reason: String,
+    pub nonce: u64,
+}
+
+/// The pool landed a solution and the challenge's rewards were split. Event id 3.
+#[derive(Clone, Debug, Serialize)]
+pub struct ChallengeSettledEvent {
+    pub pool_difficulty: u32,
+    pub pool_earned: u64,
+    pub pool_balance: u64,
+    pub miner_difficulty: u32,
+    pub miner_earned: u64,
+}
+
+/// The miner's claimable rewards balance changed. Event id 4.
+#[derive(Clone, Debug, Serialize)]
+pub struct BalanceUpdateEvent {
+    pub balance: u64,
+}
+
+/// A claim moved to a new state. Event id 5.
+#[derive(Clone, Debug, Serialize)]
+pub struct ClaimUpdateEvent {
+    pub claim_id: i32,
+    pub amount: u64,
+    pub state: String,
+    pub signature: String,
+    pub error: Option<String>,
+}
+
+/// Human readable information from the pool. Event id 6.
+#[derive(Clone, Debug, Serialize)]
+pub struct NoticeEvent {
+    pub message: String,
+}
+
+/// Counters for the current connection, pushed periodically and in reply to a stats
+/// request (client message type 3). Not sent to legacy clients. Event id 7.
+#[derive(Clone, Debug, Serialize)]
+pub struct StatsEvent {
+    pub accepted: u64,
+    pub rejected: BTreeMap<String, u64>,
+    /// Best accepted difficulty for the current challenge.
+    pub best_difficulty: u32,
+    /// Hashes per second implied by the accepted shares since connecting.
+    pub estimated_hashrate: u64,
+    pub unclaimed_balance: u64,
+    pub connected_secs: u64,
+    pub rtt_ms: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerEvent {
+    Job(JobEvent),
+    ShareAccepted(ShareAcceptedEvent),
+    ShareRejected(ShareRejectedEvent),
+    ChallengeSettled(ChallengeSettledEvent),
+    BalanceUpdate(BalanceUpdateEvent),
+    ClaimUpdate(ClaimUpdateEvent),
+    Notice(NoticeEvent),
+    Stats(StatsEvent),
+}
+
+impl ServerEvent {
+    pub fn event_id(&self) -> u8 {
+        match self {
+            ServerEvent::Job(_) => 0,
+            ServerEvent::ShareAccepted(_) => 1,
+            ServerEvent::ShareRejected(_) => 2,
+            ServerEvent::ChallengeSettled(_) => 3,
+            ServerEvent::BalanceUpdate(_) => 4,
+            ServerEvent::ClaimUpdate(_) => 5,
+            ServerEvent::Notice(_) => 6,
+            ServerEvent::Stats(_) => 7,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+
+    pub fn to_binary(&self) -> Vec<u8> {
+        let body = match self {
+            ServerEvent::Job(job) => return job_to_bytes(job).to_vec(),
+            ServerEvent::ShareAccepted(e) => bincode::serialize(e),
+            ServerEvent::ShareRejected(e) => bincode::serialize(e),
+            ServerEvent::ChallengeSettled(e) => bincode::serialize(e),
+            ServerEvent::BalanceUpdate(e) => bincode::serialize(e),
+            ServerEvent::ClaimUpdate(e) => bincode::serialize(e),
+            ServerEvent::Notice(e) => bincode::serialize(e),
+            ServerEvent::Stats(e) => bincode::serialize(e),
+        }.unwrap();
+
+        let mut data = Vec::with_capacity(body.len() + 1);
+        data.push(self.event_id());
+        data.extend_from_slice(&body);
+        data
+    }
+
+    /// The message older clients expect, if they expect one at all.
+    pub fn to_legacy_text(&self) -> Option<String> {
+        let decimals = 10f64.powf(crate::ore_utils::ORE_TOKEN_DECIMALS as f64);
+        match self {
+            ServerEvent::ChallengeSettled(e) => Some(format!(
+                "Submitted Difficulty: {}\nPool Earned: {} ORE.\nPool Balance: {}\nMiner Earned: {} ORE for difficulty: {}",
+                e.pool_difficulty,
+                e.pool_earned as f64 / decimals,
+                e.pool_balance as f64 / decimals,
+                e.miner_earned as f64 / decimals,
+                e.miner_difficulty
+            )),
+            ServerEvent::ClaimUpdate(e) => {
+                let amount_dec = e.amount as f64 / decimals;
+                if let Some(error) = &e.error {
+                    Some(format!("Claim {} of {} ORE {}: {}\nSig: {}", e.claim_id, amount_dec, e.state, error, e.signature))
+                } else {
+                    Some(format!("Claim {} of {} ORE {}.\nSig: {}", e.claim_id, amount_dec, e.state, e.signature))
+                }
+            },
+            ServerEvent::Notice(e) => Some(e.message.clone()),
+            _ => None,
+        }
+    }
+}
+
+// message type is 8 bytes = 1 u8
+// challenge is 256 bytes = 32 u8
+// cutoff is 64 bytes = 8 u8
+// nonce_range is 128 bytes, start is 64 bytes, end is 64 bytes = 16 u8
+pub fn job_to_bytes(job: &JobEvent) -> [u8; 57] {
+    let mut bin_data = [0; 57];
+    bin_data[00..1].copy_from_slice(&0u8.to_le_bytes());
+    bin_data[01..33].copy_from_slice(&job.challenge);
+    bin_data[33..41].copy_from_slice(&job.cutoff.to_le_bytes());
+    bin_data[41..49].copy_from_slice(&job.nonce_start.to_le_bytes());
+    bin_data[49..57].copy_from_slice(&job.nonce_end.to_le_bytes());
+    bin_data
+}
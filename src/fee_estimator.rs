@@ -0,0 +1,81 @@
+use ore_api::consts::BUS_ADDRESSES;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::pubkey::Pubkey;
+use tracing::error;
+
+pub struct FeeEstimatorConfig {
+    /// Percentile of recent fees to pay, 0-100.
+    pub percentile: u8,
+    pub min_fee: u64,
+    pub max_fee: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FeeSource {
+    RecentFees,
+    /// The rpc didn't return any non zero fees, the caller's heuristic fee was used.
+    Fallback,
+}
+
+#[derive(Debug)]
+pub struct FeeEstimate {
+    /// Microlamports per compute unit.
+    pub fee: u64,
+    pub source: FeeSource,
+    /// Slots that paid a non zero fee.
+    pub samples: usize,
+    /// Fee at the configured percentile before clamping.
+    pub percentile_fee: Option<u64>,
+}
+
+/// Accounts mine transactions write to, recent fees paid for these reflect
+/// what it takes to land a mine transaction.
+pub fn mine_fee_accounts() -> Vec<Pubkey> {
+    let mut accounts = vec![ore_api::ID];
+    accounts.extend_from_slice(&BUS_ADDRESSES);
+    accounts
+}
+
+/// Picks a priority fee from the fees paid for `accounts` over recent slots.
+/// Slots without any prioritized transactions report a fee of 0 and are left out, they
+/// say nothing about what it takes to land. Falls back to `fallback_fee` when the rpc
+/// doesn't support the method or every slot was 0.
+pub async fn estimate_priority_fee(
+    rpc_client: &RpcClient,
+    accounts: &[Pubkey],
+    config: &FeeEstimatorConfig,
+    fallback_fee: u64,
+) -> FeeEstimate {
+    let mut fees: Vec<u64> = match rpc_client.get_recent_prioritization_fees(accounts).await {
+        Ok(fees) => fees.into_iter().map(|f| f.prioritization_fee).filter(|fee| *fee > 0).collect(),
+        Err(e) => {
+            error!("Failed to get recent prioritization fees: {}", e);
+            Vec::new()
+        }
+    };
+
+    if fees.is_empty() {
+        return FeeEstimate {
+            fee: fallback_fee,
+            source: FeeSource::Fallback,
+            samples: 0,
+            percentile_fee: None,
+        };
+    }
+
+    fees.sort_unstable();
+    let percentile_fee = percentile(&fees, config.percentile);
+    FeeEstimate {
+        fee: percentile_fee.clamp(config.min_fee, config.max_fee.max(config.min_fee)),
+        source: FeeSource::RecentFees,
+        samples: fees.len(),
+        percentile_fee: Some(percentile_fee),
+    }
+}
+
+/// Nearest rank percentile of already sorted, non empty `values`.
+fn percentile(values: &[u64], percentile: u8) -> u64 {
+    let percentile = percentile.min(100) as usize;
+    let rank = (percentile * values.len() + 99) / 100;
+    values[rank.saturating_sub(1).min(values.len() - 1)]
+}
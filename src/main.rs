@@ -1,44 +1,285 @@
-use std::{collections::{HashMap, HashSet}, net::SocketAddr, ops::{ControlFlow, Div, Mul, Range, RangeBounds}, path::Path, str::FromStr, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
+use std::{collections::{BTreeMap, HashMap, HashSet}, net::{IpAddr, SocketAddr}, ops::{ControlFlow, Div, Mul, Range, RangeBounds}, path::Path, str::FromStr, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
 
 use app_database::{AppDatabase, AppDatabaseError};
-use axum::{extract::{ws::{Message, WebSocket}, ConnectInfo, Query, State, WebSocketUpgrade}, http::{Response, StatusCode}, response::IntoResponse, routing::{get, post}, Extension, Router};
-use axum_extra::{headers::authorization::Basic, TypedHeader};
+use bus_strategy::BusStrategyKind;
+use compute_units::ComputeUnitEstimator;
+use axum::{extract::{ws::{CloseFrame, Message, WebSocket}, ConnectInfo, Query, Request, State, WebSocketUpgrade}, http::{HeaderMap, HeaderName, HeaderValue, Response, StatusCode}, middleware::{self, Next}, response::IntoResponse, routing::{get, post}, Extension, Router};
+use axum_server::tls_rustls::RustlsConfig;
+use axum_extra::{headers::authorization::{Basic, Bearer}, TypedHeader};
 use base64::{prelude::BASE64_STANDARD, Engine};
 use clap::Parser;
 use drillx::Solution;
-use futures::{stream::SplitSink, SinkExt, StreamExt};
+use futures::{SinkExt, StreamExt};
 use ore_api::{consts::BUS_COUNT, state::Proof};
 use ::ore_utils::AccountDeserialize;
 use ore_utils::{get_auth_ix, get_cutoff, get_mine_ix, get_ore_mint, get_proof, get_proof_and_config_with_busses, get_register_ix, get_reset_ix, proof_pubkey, ORE_TOKEN_DECIMALS};
 use rand::Rng;
 use serde::Deserialize;
 use solana_account_decoder::UiAccountEncoding;
-use solana_client::{nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient}, rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig}};
-use solana_sdk::{commitment_config::CommitmentConfig, compute_budget::ComputeBudgetInstruction, native_token::LAMPORTS_PER_SOL, pubkey::Pubkey, signature::{read_keypair_file, Keypair, Signature}, signer::Signer, system_instruction, transaction::Transaction};
+use solana_client::{nonblocking::{pubsub_client::PubsubClient}, rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig}};
+use solana_sdk::{commitment_config::CommitmentConfig, compute_budget::ComputeBudgetInstruction, native_token::LAMPORTS_PER_SOL, pubkey::Pubkey, signature::{read_keypair_file, Keypair, Signature}, signer::Signer, transaction::Transaction};
 use spl_associated_token_account::get_associated_token_address;
-use tokio::{io::AsyncReadExt, sync::{mpsc::{UnboundedReceiver, UnboundedSender}, Mutex, RwLock}};
+use tokio::{io::AsyncReadExt, sync::{mpsc::{error::TrySendError, UnboundedReceiver, UnboundedSender}, Mutex, Notify, RwLock}};
 use tower_http::trace::{DefaultMakeSpan, TraceLayer};
 use tracing::{error, info};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use fee_estimator::{estimate_priority_fee, mine_fee_accounts, FeeEstimatorConfig};
+use payouts::{auto_payout_system, process_claim, reconcile_pending_claims, AutoPayoutConfig, ClaimError, MessageInternalClaimSettled, PayoutReport};
+use events::{BalanceUpdateEvent, ChallengeSettledEvent, ClaimUpdateEvent, JobEvent, NoticeEvent, ServerEvent, ShareAcceptedEvent, ShareRejectedEvent, StatsEvent, WsProtocol};
+use rpc_pool::{rpc_health_system, RpcPool};
+use rate_limit::{RateLimitConfig, RateLimitError, RateLimiter};
+use signup::{decode_signup_signature, decode_signup_transaction, signup_challenge_message, verify_onchain_signup_payment, validate_signup_payment, validate_signup_proof, SignupChallenges, SignupMode, SignupPaymentError, SignupProofError};
+use staking::{auto_stake_system, StakeConfig};
+use tls::tls_reload_system;
+use tx_manager::{TxManager, TxOutcome};
+use ws_auth::{decode_ws_nonce, encode_ws_nonce, new_resume_id, ws_auth_message, WsAuth, WS_AUTH_NONCE_MAX_AGE};
 use self::models::*;
 
 mod models;
 mod app_database;
+mod bus_strategy;
+mod compute_units;
 mod schema;
 
 
 const MIN_DIFF: u32 = 8;
 const MIN_HASHPOWER: u64 = 5;
+// Messages buffered per connection before the client is considered too slow
+const WS_SEND_QUEUE_SIZE: usize = 64;
+// Signed http requests are only valid for this many seconds
+const SIGNED_REQUEST_MAX_AGE: u64 = 30;
+// Prefixed to signed request messages, followed by one of the action bytes below so a
+// signature for one action never verifies for another
+const SIGNED_REQUEST_DOMAIN: &[u8] = b"ore-hq-server request:";
+const SIGNED_ACTION_SET_PAYOUT_ADDRESS: u8 = 0;
+const SIGNED_ACTION_CONFIRM_PAYOUT_ADDRESS: u8 = 1;
+const SIGNED_ACTION_PAYOUT_THRESHOLD: u8 = 2;
+const SIGNED_ACTION_REFERRAL_CODE: u8 = 3;
+const MAX_CLAIMS_PAGE_SIZE: u64 = 100;
+// Signup challenges must be answered within this many seconds
+const SIGNUP_CHALLENGE_MAX_AGE: u64 = 300;
+const REFERRAL_CODE_LEN: usize = 8;
+const DEFAULT_WORKER_NAME: &str = "default";
+const MAX_WORKER_NAME_LEN: usize = 32;
+// Window used for the per worker stats returned by /miner/workers
+const WORKER_STATS_WINDOW: u64 = 86400;
+// Stats requests from a connection more often than this are ignored
+const STATS_REQUEST_MIN_INTERVAL: Duration = Duration::from_secs(5);
+
+
+struct AppClientConnection {
+    pubkey: Pubkey,
+    miner_id: i32,
+    worker: String,
+    ip: IpAddr,
+    protocol: WsProtocol,
+    health: Arc<ConnectionHealth>,
+    // queue drained by the connection's writer task
+    sender: tokio::sync::mpsc::Sender<Message>,
+    // tells the writer task to drop the connection without draining its queue
+    close: Arc<Notify>,
+    // lets the client pick up its nonce assignment after a reconnect
+    resume_id: Option<String>,
+    stats: std::sync::Mutex<SessionStats>,
+    stats_requested_at: std::sync::Mutex<Option<Instant>>,
+}
+
+impl AppClientConnection {
+    /// Queues `msg` for the writer task without waiting on the client.
+    /// A client whose queue is full can't keep up and gets disconnected.
+    fn send(&self, msg: Message) -> bool {
+        match self.sender.try_send(msg) {
+            Ok(_) => true,
+            Err(TrySendError::Full(_)) => {
+                error!("Send queue full for {} worker {}, disconnecting", self.pubkey, self.worker);
+                self.close.notify_one();
+                false
+            },
+            Err(TrySendError::Closed(_)) => false,
+        }
+    }
+
+    /// Encodes `event` for the protocol negotiated by this client and queues it.
+    /// Events legacy clients don't understand are skipped.
+    fn send_event(&self, event: &ServerEvent) -> bool {
+        let msg = match (self.protocol, event) {
+            (WsProtocol::Json, _) => Message::Text(event.to_json()),
+            (WsProtocol::Binary, _) => Message::Binary(event.to_binary()),
+            (WsProtocol::Legacy, ServerEvent::Job(_)) => Message::Binary(event.to_binary()),
+            (WsProtocol::Legacy, _) => {
+                if let Some(text) = event.to_legacy_text() {
+                    Message::Text(text)
+                } else {
+                    return true;
+                }
+            }
+        };
+        self.send(msg)
+    }
+
+    /// Whether a stats request from this client should be answered. Legacy clients can't
+    /// receive stats and everyone else is throttled to one request per `STATS_REQUEST_MIN_INTERVAL`.
+    fn allow_stats_request(&self) -> bool {
+        if self.protocol == WsProtocol::Legacy {
+            return false;
+        }
+        let mut requested_at = self.stats_requested_at.lock().unwrap();
+        if requested_at.map_or(false, |at| at.elapsed() < STATS_REQUEST_MIN_INTERVAL) {
+            return false;
+        }
+        *requested_at = Some(Instant::now());
+        true
+    }
+}
 
+/// How a websocket client proved who it is.
+enum WsAuthMethod {
+    /// A session token, the replacement token keeps this expiry.
+    Session(Instant),
+    Nonce,
+    /// A signature over just the timestamp, replayable so no session token is issued for it.
+    LegacyTimestamp,
+}
+
+/// Identity of an authenticated websocket client, handed from `ws_handler` to `handle_socket`.
+struct WsClient {
+    pubkey: Pubkey,
+    miner_id: i32,
+    worker: String,
+    protocol: WsProtocol,
+    // issued for this connection
+    resume_id: Option<String>,
+    // sent by the client to pick up where an earlier connection left off
+    resume_from: Option<String>,
+    // client ip the rate limits apply to, the proxy's client ip when behind one
+    ip: IpAddr,
+}
+
+/// A nonce range handed to a client along with the challenge it was handed out for.
+#[derive(Clone)]
+struct NonceAssignment {
+    challenge: [u8; 32],
+    range: Range<u64>,
+}
+
+/// The assignment of a dropped connection, held until the client resumes or `expires_at` passes.
+struct ParkedSession {
+    miner_id: i32,
+    worker: String,
+    assignment: NonceAssignment,
+    stats: SessionStats,
+    expires_at: Instant,
+}
 
 struct AppState {
-    sockets: HashMap<SocketAddr, (Pubkey, Arc<Mutex<SplitSink<WebSocket, Message>>>)>,
-    miner_sockets: HashMap<i32, (Pubkey, Arc<Mutex<SplitSink<WebSocket, Message>>>)>
+    sockets: HashMap<SocketAddr, AppClientConnection>,
+    // worker name -> socket addr, for each connected miner
+    miner_sockets: HashMap<i32, HashMap<String, SocketAddr>>,
+    ready_clients: HashSet<SocketAddr>,
+    nonce_ranges: HashMap<SocketAddr, NonceAssignment>,
+    // resume id -> assignment of a recently dropped connection
+    parked_sessions: HashMap<String, ParkedSession>,
+    resume_grace: Duration,
+}
+
+/// Liveness of a connection, updated by its reader task whenever a pong comes back.
+struct ConnectionHealth {
+    last_pong: std::sync::Mutex<Instant>,
+    // 0 until a pong echoing one of our timestamped pings is received
+    rtt_ms: AtomicU64,
+}
+
+impl ConnectionHealth {
+    fn new() -> Self {
+        ConnectionHealth {
+            last_pong: std::sync::Mutex::new(Instant::now()),
+            rtt_ms: AtomicU64::new(0),
+        }
+    }
+
+    fn record_pong(&self, payload: &[u8]) {
+        *self.last_pong.lock().unwrap() = Instant::now();
+        if let Ok(sent_at) = <[u8; 8]>::try_from(payload) {
+            let rtt = now_millis().saturating_sub(u64::from_le_bytes(sent_at));
+            self.rtt_ms.store(rtt.max(1), Ordering::Relaxed);
+        }
+    }
+
+    fn idle_for(&self) -> Duration {
+        self.last_pong.lock().unwrap().elapsed()
+    }
+
+    fn rtt_ms(&self) -> Option<u64> {
+        match self.rtt_ms.load(Ordering::Relaxed) {
+            0 => None,
+            rtt => Some(rtt),
+        }
+    }
+}
+
+/// Share counters for a connection, carried over when the session is resumed.
+struct SessionStats {
+    connected_at: Instant,
+    // challenge best_difficulty was reached on
+    challenge: [u8; 32],
+    accepted: u64,
+    rejected: BTreeMap<&'static str, u64>,
+    best_difficulty: u32,
+    // expected hashes needed to find the accepted shares, 2^difficulty each
+    hashes: f64,
+}
+
+impl SessionStats {
+    fn new() -> Self {
+        SessionStats {
+            connected_at: Instant::now(),
+            challenge: [0; 32],
+            accepted: 0,
+            rejected: BTreeMap::new(),
+            best_difficulty: 0,
+            hashes: 0.0,
+        }
+    }
+
+    /// Resets the best difficulty when the client is handed work for a new challenge.
+    fn start_job(&mut self, challenge: [u8; 32]) {
+        if self.challenge != challenge {
+            self.challenge = challenge;
+            self.best_difficulty = 0;
+        }
+    }
+
+    fn record_accepted(&mut self, difficulty: u32) {
+        self.accepted += 1;
+        self.best_difficulty = self.best_difficulty.max(difficulty);
+        self.hashes += 2f64.powi(difficulty as i32);
+    }
+
+    fn record_rejected(&mut self, reason: &'static str) {
+        *self.rejected.entry(reason).or_insert(0) += 1;
+    }
+
+    fn to_event(&self, unclaimed_balance: u64, rtt_ms: Option<u64>) -> ServerEvent {
+        let connected_secs = self.connected_at.elapsed().as_secs();
+        ServerEvent::Stats(StatsEvent {
+            accepted: self.accepted,
+            rejected: self.rejected.iter().map(|(reason, count)| (reason.to_string(), *count)).collect(),
+            best_difficulty: self.best_difficulty,
+            estimated_hashrate: (self.hashes / connected_secs.max(1) as f64) as u64,
+            unclaimed_balance,
+            connected_secs,
+            rtt_ms,
+        })
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_millis() as u64
 }
 
 pub struct MessageInternalMineSuccess {
     difficulty: u32,
-    total_balance: f64,
+    pool_balance: u64,
     pool_id: i32,
     rewards: u64,
     total_hashpower: u64,
@@ -49,16 +290,38 @@ pub struct MessageInternalMineSuccess {
 pub enum ClientMessage {
     Ready(SocketAddr),
     Mining(SocketAddr),
-    BestSolution(SocketAddr, Solution, Pubkey)
+    BestSolution(SocketAddr, Solution, Pubkey),
+    StatsRequest(SocketAddr),
 }
 
 pub struct Config {
     password: String,
-    whitelist: Option<HashSet<Pubkey>>,
     pool_id: i32,
+    signup_cost: u64,
+    signup_mode: SignupMode,
+    signup_pow_difficulty: u32,
+    payout_address_timelock: u64,
+    auto_payout_threshold: u64,
+    pool_commission: u64,
+    referral_percent: u64,
+    max_workers_per_wallet: usize,
+    ws_auth_max_skew: u64,
+    ws_session_ttl: u64,
+    ws_legacy_auth: bool,
+    ws_resume_grace: u64,
 }
 
 mod ore_utils;
+mod payouts;
+mod rpc_pool;
+mod events;
+mod fee_estimator;
+mod rate_limit;
+mod signup;
+mod staking;
+mod tls;
+mod tx_manager;
+mod ws_auth;
 
 #[derive(Parser, Debug)]
 #[command(version, author, about, long_about = None)]
@@ -71,10 +334,84 @@ struct Args {
         global = true
     )]
     priority_fee: u64,
+    #[arg(
+        long,
+        value_name = "dynamic priority fee",
+        help = "Price mine transactions from recent prioritization fees instead of adjusting --priority-fee on success and failure",
+        default_value = "false",
+        global = true
+    )]
+    dynamic_priority_fee: bool,
+    #[arg(
+        long,
+        value_name = "priority fee percentile",
+        help = "Percentile of recent prioritization fees to pay with --dynamic-priority-fee",
+        default_value = "75",
+        value_parser = clap::value_parser!(u8).range(0..=100),
+        global = true
+    )]
+    priority_fee_percentile: u8,
+    #[arg(
+        long,
+        value_name = "min priority fee",
+        help = "Lowest priority fee in microlamports --dynamic-priority-fee will pay",
+        default_value = "0",
+        global = true
+    )]
+    min_priority_fee: u64,
+    #[arg(
+        long,
+        value_name = "max priority fee",
+        help = "Highest priority fee in microlamports --dynamic-priority-fee will pay",
+        default_value = "1000000",
+        global = true
+    )]
+    max_priority_fee: u64,
+    #[arg(
+        long,
+        value_name = "rpc broadcast count",
+        help = "Number of rpc endpoints each transaction is sent to in parallel",
+        default_value = "3",
+        global = true
+    )]
+    rpc_broadcast_count: usize,
+    #[arg(
+        long,
+        value_name = "rpc health check interval",
+        help = "Seconds between rpc endpoint health checks",
+        default_value = "10",
+        global = true
+    )]
+    rpc_health_check_interval: u64,
+    #[arg(
+        long,
+        value_name = "compute unit margin",
+        help = "Percent added to simulated compute units when setting a transaction's compute unit limit",
+        default_value = "10",
+        global = true
+    )]
+    compute_unit_margin: u32,
+    #[arg(
+        long,
+        value_name = "tx rebroadcast interval",
+        help = "Milliseconds between status polls and rebroadcasts of a sent transaction",
+        default_value = "400",
+        global = true
+    )]
+    tx_rebroadcast_interval_ms: u64,
+    #[arg(
+        long,
+        value_name = "bus strategy",
+        help = "How the bus for each mine transaction is picked",
+        value_enum,
+        default_value = "richest",
+        global = true
+    )]
+    bus_strategy: BusStrategyKind,
     #[arg(
         long,
         value_name = "whitelist",
-        help = "Path to whitelist of allowed miners",
+        help = "Path to a whitelist of allowed miners to import into the database",
         default_value = None,
         global = true
     )]
@@ -82,14 +419,283 @@ struct Args {
     #[arg(
         long,
         value_name = "signup cost",
-        help = "Amount of sol users must send to sign up for the pool",
-        default_value = "0",
+        help = "Amount of lamports users must send to sign up for the pool",
+        default_value = "1000000",
         global = true
     )]
     signup_cost: u64,
+    #[arg(
+        long,
+        value_name = "signup mode",
+        help = "How non whitelisted users sign up: by paying the signup cost or by signing a challenge",
+        value_enum,
+        default_value = "payment",
+        global = true
+    )]
+    signup_mode: SignupMode,
+    #[arg(
+        long,
+        value_name = "signup pow difficulty",
+        help = "Minimum drillx difficulty required with signature signups, 0 disables the proof of work",
+        default_value = "0",
+        global = true
+    )]
+    signup_pow_difficulty: u32,
+    #[arg(
+        long,
+        value_name = "payout address timelock",
+        help = "Seconds a miner must wait before confirming a new payout address, including the first one",
+        default_value = "86400",
+        global = true
+    )]
+    payout_address_timelock: u64,
+    #[arg(
+        long,
+        value_name = "auto payout interval",
+        help = "Seconds between automatic payout runs, 0 disables automatic payouts",
+        default_value = "0",
+        global = true
+    )]
+    auto_payout_interval: u64,
+    #[arg(
+        long,
+        value_name = "auto payout threshold",
+        help = "Default and minimum rewards balance, in base units, before a miner is paid out automatically",
+        default_value = "10000000000",
+        global = true
+    )]
+    auto_payout_threshold: u64,
+    #[arg(
+        long,
+        value_name = "auto payout max per run",
+        help = "Maximum number of miners paid out in a single automatic payout run",
+        default_value = "25",
+        global = true
+    )]
+    auto_payout_max_per_run: u64,
+    #[arg(
+        long,
+        value_name = "auto payout max amount per run",
+        help = "Maximum total base units paid out in a single automatic payout run, 0 for no limit",
+        default_value = "0",
+        global = true
+    )]
+    auto_payout_max_amount_per_run: u64,
+    #[arg(
+        long,
+        value_name = "stake percent",
+        help = "Percent of the pool's unstaked rewards surplus to stake from the pool wallet each run, 0 disables auto staking",
+        default_value = "0",
+        global = true
+    )]
+    stake_percent: u64,
+    #[arg(
+        long,
+        value_name = "stake reserve",
+        help = "Base units kept unstaked on top of outstanding miner rewards",
+        default_value = "0",
+        global = true
+    )]
+    stake_reserve: u64,
+    #[arg(
+        long,
+        value_name = "stake interval",
+        help = "Seconds between auto stake runs",
+        default_value = "3600",
+        global = true
+    )]
+    stake_interval: u64,
+    #[arg(
+        long,
+        value_name = "stake min amount",
+        help = "Smallest amount, in base units, worth sending a stake transaction for",
+        default_value = "1000000000",
+        global = true
+    )]
+    stake_min_amount: u64,
+    #[arg(
+        long,
+        value_name = "pool commission",
+        help = "Percent of each mining reward kept by the pool",
+        default_value = "0",
+        global = true
+    )]
+    pool_commission: u64,
+    #[arg(
+        long,
+        value_name = "referral percent",
+        help = "Percent of a referred miner's earnings credited to their referrer, paid out of the pool commission",
+        default_value = "0",
+        global = true
+    )]
+    referral_percent: u64,
+    #[arg(
+        long,
+        value_name = "max workers per wallet",
+        help = "Maximum number of workers that can be connected with the same wallet at once",
+        default_value = "10",
+        global = true
+    )]
+    max_workers_per_wallet: usize,
+    #[arg(
+        long,
+        value_name = "ws auth max skew",
+        help = "Seconds a websocket auth timestamp may differ from the server clock",
+        default_value = "5",
+        global = true
+    )]
+    ws_auth_max_skew: u64,
+    #[arg(
+        long,
+        value_name = "ws session ttl",
+        help = "Seconds a websocket session token can be used to reconnect, 0 disables session tokens",
+        default_value = "300",
+        global = true
+    )]
+    ws_session_ttl: u64,
+    #[arg(
+        long,
+        value_name = "ws legacy auth",
+        help = "Also accept websocket clients that sign only the timestamp without fetching a nonce. Deprecated, on by default until clients have moved to /ws/nonce, pass false to turn it off",
+        default_value = "true",
+        action = clap::ArgAction::Set,
+        global = true
+    )]
+    ws_legacy_auth: bool,
+    #[arg(
+        long,
+        value_name = "ws idle timeout",
+        help = "Seconds without a pong before a websocket connection is dropped",
+        default_value = "30",
+        global = true
+    )]
+    ws_idle_timeout: u64,
+    #[arg(
+        long,
+        value_name = "ws resume grace",
+        help = "Seconds a dropped miner can reconnect and keep its nonce range, 0 disables session resumption",
+        default_value = "30",
+        global = true
+    )]
+    ws_resume_grace: u64,
+    #[arg(
+        long,
+        value_name = "ws stats interval",
+        help = "Seconds between stats pushed to each miner, 0 only sends stats when requested",
+        default_value = "30",
+        global = true
+    )]
+    ws_stats_interval: u64,
+    #[arg(
+        long,
+        value_name = "rate limit ip rps",
+        help = "Requests per second allowed from a single ip",
+        default_value = "10",
+        global = true
+    )]
+    rate_limit_ip_rps: f64,
+    #[arg(
+        long,
+        value_name = "rate limit ip burst",
+        help = "Requests a single ip can burst above its per second limit",
+        default_value = "30",
+        global = true
+    )]
+    rate_limit_ip_burst: f64,
+    #[arg(
+        long,
+        value_name = "rate limit pubkey rps",
+        help = "Connection attempts, signups and claims per second allowed for a single pubkey",
+        default_value = "1",
+        global = true
+    )]
+    rate_limit_pubkey_rps: f64,
+    #[arg(
+        long,
+        value_name = "rate limit pubkey burst",
+        help = "Requests a single pubkey can burst above its per second limit",
+        default_value = "10",
+        global = true
+    )]
+    rate_limit_pubkey_burst: f64,
+    #[arg(
+        long,
+        value_name = "ban threshold",
+        help = "Failed websocket auths or invalid solutions from an ip before it is banned",
+        default_value = "10",
+        global = true
+    )]
+    ban_threshold: u32,
+    #[arg(
+        long,
+        value_name = "ban strike window",
+        help = "Seconds over which failures are counted towards a ban",
+        default_value = "300",
+        global = true
+    )]
+    ban_strike_window: u64,
+    #[arg(
+        long,
+        value_name = "ban duration",
+        help = "Seconds an ip stays banned",
+        default_value = "900",
+        global = true
+    )]
+    ban_duration: u64,
+    #[arg(
+        long,
+        value_name = "max connections per ip",
+        help = "Maximum number of websocket connections from a single ip",
+        default_value = "32",
+        global = true
+    )]
+    max_connections_per_ip: usize,
+    #[arg(
+        long,
+        value_name = "trusted proxy header",
+        help = "Header a reverse proxy sets to the client ip, such as x-forwarded-for. Only set this when every request comes through the proxy",
+        default_value = None,
+        global = true
+    )]
+    trusted_proxy_header: Option<String>,
+    #[arg(
+        long,
+        value_name = "tls cert",
+        help = "Path to a PEM certificate chain. Serves HTTPS and WSS when set together with --tls-key",
+        default_value = None,
+        requires = "tls_key",
+        global = true
+    )]
+    tls_cert: Option<String>,
+    #[arg(
+        long,
+        value_name = "tls key",
+        help = "Path to the PEM private key for --tls-cert",
+        default_value = None,
+        requires = "tls_cert",
+        global = true
+    )]
+    tls_key: Option<String>,
+    #[arg(
+        long,
+        value_name = "tls reload interval",
+        help = "Seconds between checks for a changed TLS certificate or key",
+        default_value = "60",
+        global = true
+    )]
+    tls_reload_interval: u64,
 }
 
 
+/// `primary` followed by the non empty entries of a comma separated list.
+fn url_list(primary: String, extra: Option<String>) -> Vec<String> {
+    let mut urls = vec![primary];
+    if let Some(extra) = extra {
+        urls.extend(extra.split(',').map(|url| url.trim().to_string()).filter(|url| !url.is_empty()));
+    }
+    urls
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv::dotenv().ok();
@@ -106,41 +712,38 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let wallet_path_str = std::env::var("WALLET_PATH").expect("WALLET_PATH must be set.");
     let rpc_url = std::env::var("RPC_URL").expect("RPC_URL must be set.");
     let rpc_ws_url = std::env::var("RPC_WS_URL").expect("RPC_WS_URL must be set.");
+    // optional comma separated fallback endpoints
+    let rpc_urls = url_list(rpc_url, std::env::var("RPC_URLS").ok());
+    let rpc_ws_urls = url_list(rpc_ws_url, std::env::var("RPC_WS_URLS").ok());
     let password = std::env::var("PASSWORD").expect("PASSWORD must be set.");
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set.");
 
     let app_database = Arc::new(AppDatabase::new(database_url));
 
 
-    let whitelist = if let Some(whitelist) = args.whitelist {
+    if let Some(whitelist) = args.whitelist {
         let file = Path::new(&whitelist);
         if file.exists() {
             // load file
-            let mut pubkeys = HashSet::new();
             if let Ok(mut file) = tokio::fs::File::open(file).await {
                 let mut file_contents = String::new();
                 file.read_to_string(&mut file_contents).await.ok().expect("Failed to read whitelist file");
                 drop(file);
 
-                for (i, line) in file_contents.lines().enumerate() {
-                    if let Ok(pubkey) = Pubkey::from_str(line) {
-                        pubkeys.insert(pubkey);
-                    } else {
-                        let err = format!("Failed to create pubkey from line {} with value: {}", i, line);
-                        error!(err);
+                let pubkeys = parse_whitelist(&file_contents);
+                for pubkey in pubkeys.iter() {
+                    if app_database.add_whitelist_entry(pubkey.to_string()).await.is_err() {
+                        return Err("Failed to import whitelist into database".into());
                     }
-
                 }
+                info!("Imported {} whitelist entries", pubkeys.len());
             } else {
                 return Err("Failed to open whitelist file".into());
             }
-            Some(pubkeys)
         } else {
             return Err("Whitelist at specified file path doesn't exist".into());
         }
-    } else {
-        None
-    };
+    }
 
     let priority_fee = Arc::new(Mutex::new(args.priority_fee));
 
@@ -157,7 +760,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
 
     println!("establishing rpc connection...");
-    let rpc_client = RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed());
+    let rpc_pool = Arc::new(RpcPool::new(rpc_urls, rpc_ws_urls, args.rpc_broadcast_count));
+    let rpc_client = rpc_pool.client();
+    let compute_unit_estimator = Arc::new(ComputeUnitEstimator::new(args.compute_unit_margin));
+    let tx_manager = Arc::new(TxManager::new(rpc_pool.clone(), compute_unit_estimator.clone(), Duration::from_millis(args.tx_rebroadcast_interval_ms)));
 
 
     println!("loading sol balance...");
@@ -187,14 +793,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
             tx.sign(&[&wallet], hash);
 
-            let result = rpc_client
-                .send_and_confirm_transaction_with_spinner_and_commitment(
-                    &tx, rpc_client.commitment()
-                ).await;
+            let report = tx_manager.send(&tx, rpc_client.commitment()).await;
 
-            if let Ok(sig) = result {
-                println!("Sig: {}", sig.to_string());
+            if report.landed() {
+                println!("Sig: {}", report.signature.to_string());
             } else {
+                error!("Proof account creation {}", report.outcome);
                 return Err("Failed to create proof account".into());
             }
         }
@@ -255,55 +859,109 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let config = Arc::new(Config {
         password,
-        whitelist,
         pool_id: db_pool.id,
+        signup_cost: args.signup_cost,
+        signup_mode: args.signup_mode,
+        signup_pow_difficulty: args.signup_pow_difficulty,
+        payout_address_timelock: args.payout_address_timelock,
+        auto_payout_threshold: args.auto_payout_threshold,
+        pool_commission: args.pool_commission.min(100),
+        referral_percent: args.referral_percent.min(100),
+        max_workers_per_wallet: args.max_workers_per_wallet.max(1),
+        ws_auth_max_skew: args.ws_auth_max_skew,
+        ws_session_ttl: args.ws_session_ttl,
+        ws_legacy_auth: args.ws_legacy_auth,
+        ws_resume_grace: args.ws_resume_grace,
     });
 
     let wallet_extension = Arc::new(wallet);
     let proof_ext = Arc::new(Mutex::new(proof));
     let nonce_ext = Arc::new(Mutex::new(0u64));
 
-    let client_nonce_ranges = Arc::new(RwLock::new(HashMap::new()));
+    let signup_challenges = Arc::new(Mutex::new(SignupChallenges::default()));
+    let ws_auth = Arc::new(Mutex::new(WsAuth::default()));
+    let used_signatures = Arc::new(Mutex::new(UsedSignatures::default()));
+    let client_ip_header = match args.trusted_proxy_header.as_deref().map(HeaderName::from_str).transpose() {
+        Ok(header) => header,
+        Err(_) => {
+            return Err("Invalid trusted proxy header.".into());
+        }
+    };
+    let rate_limiter = Arc::new(Mutex::new(RateLimiter::new(RateLimitConfig {
+        ip_rate: args.rate_limit_ip_rps,
+        ip_burst: args.rate_limit_ip_burst,
+        pubkey_rate: args.rate_limit_pubkey_rps,
+        pubkey_burst: args.rate_limit_pubkey_burst,
+        ban_threshold: args.ban_threshold.max(1),
+        strike_window: Duration::from_secs(args.ban_strike_window),
+        ban_duration: Duration::from_secs(args.ban_duration),
+        max_connections_per_ip: args.max_connections_per_ip,
+        client_ip_header,
+    })));
+
+    let app_rate_limiter = rate_limiter.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(60)).await;
+            app_rate_limiter.lock().await.prune();
+        }
+    });
 
     let shared_state = Arc::new(RwLock::new(AppState {
         sockets: HashMap::new(),
         miner_sockets: HashMap::new(),
+        ready_clients: HashSet::new(),
+        nonce_ranges: HashMap::new(),
+        parked_sessions: HashMap::new(),
+        resume_grace: Duration::from_secs(args.ws_resume_grace),
     }));
-    let ready_clients = Arc::new(Mutex::new(HashSet::new()));
 
+    let app_rpc_pool = rpc_pool.clone();
+    tokio::spawn(async move {
+        rpc_health_system(app_rpc_pool, Duration::from_secs(args.rpc_health_check_interval)).await;
+    });
+
+    let app_rpc_pool = rpc_pool.clone();
     let app_wallet = wallet_extension.clone();
     let app_proof = proof_ext.clone();
     // Establish webocket connection for tracking pool proof changes.
     tokio::spawn(async move {
-        proof_tracking_system(rpc_ws_url, app_wallet, app_proof).await;
+        proof_tracking_system(app_rpc_pool, app_wallet, app_proof).await;
     });
 
     let (client_message_sender, client_message_receiver) = tokio::sync::mpsc::unbounded_channel::<ClientMessage>();
 
     // Handle client messages
     let app_shared_state = shared_state.clone();
-    let app_ready_clients = ready_clients.clone();
     let app_proof = proof_ext.clone();
     let app_app_database = app_database.clone();
-    let app_client_nonce_ranges = client_nonce_ranges.clone();
+    let app_rate_limiter = rate_limiter.clone();
     tokio::spawn(async move {
-        client_message_handler_system(client_message_receiver, &app_shared_state, app_app_database, app_ready_clients, app_proof, app_client_nonce_ranges).await;
+        client_message_handler_system(client_message_receiver, &app_shared_state, app_app_database, app_proof, app_rate_limiter).await;
     });
 
+    if args.ws_stats_interval > 0 {
+        let app_shared_state = shared_state.clone();
+        let app_app_database = app_database.clone();
+        let ws_stats_interval = Duration::from_secs(args.ws_stats_interval);
+        tokio::spawn(async move {
+            stats_push_system(&app_shared_state, app_app_database, ws_stats_interval).await;
+        });
+    }
+
     // Handle ready clients
     let app_shared_state = shared_state.clone();
     let app_proof = proof_ext.clone();
     let app_nonce = nonce_ext.clone();
     let app_app_database = app_database.clone();
-    let app_client_nonce_ranges = client_nonce_ranges.clone();
     tokio::spawn(async move {
         let app_database = app_app_database;
         loop {
 
             let mut clients = Vec::new();
             {
-                let ready_clients_lock = ready_clients.lock().await;
-                for ready_client in ready_clients_lock.iter() {
+                let shared_state = app_shared_state.read().await;
+                for ready_client in shared_state.ready_clients.iter() {
                     clients.push(ready_client.clone());
                 }
             };
@@ -337,23 +995,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         start..end
                     };
                     {
-                        let shared_state = app_shared_state.read().await;
-                        // message type is 8 bytes = 1 u8
-                        // challenge is 256 bytes = 32 u8
-                        // cutoff is 64 bytes = 8 u8
-                        // nonce_range is 128 bytes, start is 64 bytes, end is 64 bytes = 16 u8
-                        let mut bin_data = [0; 57];
-                        bin_data[00..1].copy_from_slice(&0u8.to_le_bytes());
-                        bin_data[01..33].copy_from_slice(&challenge);
-                        bin_data[33..41].copy_from_slice(&cutoff.to_le_bytes());
-                        bin_data[41..49].copy_from_slice(&nonce_range.start.to_le_bytes());
-                        bin_data[49..57].copy_from_slice(&nonce_range.end.to_le_bytes());
-
-
-                        if let Some(sender) = shared_state.sockets.get(&client) {
-                            let _ = sender.1.lock().await.send(Message::Binary(bin_data.to_vec())).await;
-                            let _ = ready_clients.lock().await.remove(&client);
-                            let _ = app_client_nonce_ranges.write().await.insert(sender.0, nonce_range);
+                        let mut shared_state = app_shared_state.write().await;
+                        let job = ServerEvent::Job(JobEvent {
+                            challenge,
+                            cutoff,
+                            nonce_start: nonce_range.start,
+                            nonce_end: nonce_range.end,
+                        });
+
+                        if let Some(connection) = shared_state.sockets.get(&client) {
+                            connection.stats.lock().unwrap().start_job(challenge);
+                            connection.send_event(&job);
+                            shared_state.ready_clients.remove(&client);
+                            shared_state.nonce_ranges.insert(client, NonceAssignment {
+                                challenge,
+                                range: nonce_range,
+                            });
                         }
                     }
                 }
@@ -365,18 +1022,32 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let (mine_success_sender, mut mine_success_receiver) = tokio::sync::mpsc::unbounded_channel::<MessageInternalMineSuccess>();
 
-    let rpc_client = Arc::new(rpc_client);
     let app_proof = proof_ext.clone();
     let app_wallet = wallet_extension.clone();
     let app_nonce = nonce_ext.clone();
     let app_prio_fee = priority_fee.clone();
-    let app_rpc_client = rpc_client.clone();
+    let app_rpc_pool = rpc_pool.clone();
+    let app_compute_unit_estimator = compute_unit_estimator.clone();
+    let app_tx_manager = tx_manager.clone();
     let app_config = config.clone();
     let app_app_database = app_database.clone();
+    let bus_strategy_kind = args.bus_strategy;
+    let fee_estimator_config = if args.dynamic_priority_fee {
+        Some(FeeEstimatorConfig {
+            percentile: args.priority_fee_percentile,
+            min_fee: args.min_priority_fee,
+            max_fee: args.max_priority_fee,
+        })
+    } else {
+        None
+    };
     tokio::spawn(async move {
-        let rpc_client = app_rpc_client;
+        let rpc_pool = app_rpc_pool;
         let app_database = app_app_database;
+        let fee_accounts = mine_fee_accounts();
+        let mut bus_strategy = bus_strategy_kind.build();
         loop {
+            let rpc_client = rpc_pool.client();
             let mut old_proof = {
                 app_proof.lock().await.clone()
             };
@@ -392,37 +1063,44 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     let mut bus = rand::thread_rng().gen_range(0..BUS_COUNT);
                     let mut loaded_config = None;
                     if let (Ok(_), Ok(config), Ok(busses)) = get_proof_and_config_with_busses(&rpc_client, signer.pubkey()).await {
-                        
-                        let mut best_bus = 0;
-                        for (i, bus) in busses.iter().enumerate() {
-                            if let Ok(bus) = bus {
-                                if bus.rewards > busses[best_bus].unwrap().rewards {
-                                    best_bus = i;
-                                }
-                            }
+                        if let Some(selected_bus) = bus_strategy.select(&busses) {
+                            bus = selected_bus;
+                            info!("Selected bus {} with the {} strategy", bus, bus_strategy_kind.as_str());
+                        } else {
+                            error!("No bus accounts loaded, using random bus {}", bus);
                         }
-                        bus = best_bus;
                         loaded_config = Some(config);
                     }
 
                     let difficulty = solution.to_hash().difficulty();
 
-                    info!("Starting mine submission attempts with difficulty {}.", difficulty);
-                        
-                    for i in 0..3 {
+                    info!("Starting mine submission with difficulty {}.", difficulty);
+
+                    // re-signed with a fresh blockhash whenever the previous one expired
+                    let landed = loop {
                         let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
                         let mut ixs = vec![];
-                        let prio_fee = {
+                        let heuristic_fee = {
                             app_prio_fee.lock().await.clone()
                         };
-
-                        info!("using priority fee of {}", prio_fee);
-
-                        let cu_limit_ix = ComputeBudgetInstruction::set_compute_unit_limit(480000);
-                        ixs.push(cu_limit_ix);
-
-                        let prio_fee_ix = ComputeBudgetInstruction::set_compute_unit_price(prio_fee);
-                        ixs.push(prio_fee_ix);
+                        let prio_fee = if let Some(fee_estimator_config) = &fee_estimator_config {
+                            let estimate = estimate_priority_fee(&rpc_client, &fee_accounts, fee_estimator_config, heuristic_fee).await;
+                            info!(
+                                "using priority fee of {} ({:?}, p{} of {} samples: {:?}, bounds: {}..={}, heuristic: {})",
+                                estimate.fee,
+                                estimate.source,
+                                fee_estimator_config.percentile,
+                                estimate.samples,
+                                estimate.percentile_fee,
+                                fee_estimator_config.min_fee,
+                                fee_estimator_config.max_fee,
+                                heuristic_fee
+                            );
+                            estimate.fee
+                        } else {
+                            info!("using priority fee of {}", heuristic_fee);
+                            heuristic_fee
+                        };
 
                         let noop_ix = get_auth_ix(signer.pubkey());
                         ixs.push(noop_ix);
@@ -438,126 +1116,164 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         let ix_mine = get_mine_ix(signer.pubkey(), solution, bus);
                         ixs.push(ix_mine);
 
-                        if let Ok((hash, _slot)) = rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment()).await {
-                            let mut tx = Transaction::new_with_payer(&ixs, Some(&signer.pubkey()));
-
-                            tx.sign(&[&signer], hash);
-                            info!("Sending signed tx...");
-                            info!("attempt: {}", i + 1);
-                            let sig = rpc_client.send_and_confirm_transaction_with_spinner(&tx).await;
-                            if let Ok(sig) = sig {
-                                // success
-                                info!("Success!!");
-                                info!("Sig: {}", sig);
-                                let itxn = InsertTxn {
-                                    txn_type: "mine".to_string(),
-                                    signature: sig.to_string(),
-                                    priority_fee: prio_fee as u32,
-                                };
-                                let _ = app_database.add_new_txn(itxn).await.unwrap();
-                                // update proof
-                                loop {
-                                    info!("Waiting for proof hash update");
-                                    let latest_proof = {
-                                        app_proof.lock().await.clone()
-                                    };
+                        let compute_units = app_compute_unit_estimator.estimate(&rpc_client, &signer.pubkey(), &ixs).await;
+                        let cu_limit = compute_units.map_or(480000, |units| units.limit);
+                        info!("using compute unit limit of {}", cu_limit);
 
-                                    if old_proof.challenge.eq(&latest_proof.challenge) {
-                                        info!("Proof challenge not updated yet..");
-                                        old_proof = latest_proof;
-                                        tokio::time::sleep(Duration::from_millis(1000)).await;
-                                        continue;
-                                    } else {
-                                        info!("Proof challenge updated! Checking rewards earned.");
-                                            let balance = (latest_proof.balance as f64) / 10f64.powf(ORE_TOKEN_DECIMALS as f64);
-                                            info!("New balance: {}", balance);
-                                            let rewards = latest_proof.balance - old_proof.balance;
-                                            let dec_rewards = (rewards as f64) / 10f64.powf(ORE_TOKEN_DECIMALS as f64);
-                                            info!("Earned: {} ORE", dec_rewards);
-
-                                            let submission_id = app_database.get_submission_id_with_nonce(u64::from_le_bytes(solution.n)).await.unwrap();
-
-                                            let _ = app_database.update_challenge_rewards(old_proof.challenge.to_vec(), submission_id, rewards).await.unwrap();
-                                            let _ = app_database.update_pool_rewards(app_wallet.pubkey().to_string(), rewards).await.unwrap();
-
-                                            let submissions = app_database.get_all_submission_for_challenge(old_proof.challenge.to_vec()).await.unwrap();
-
-                                            let mut total_hashpower: u64 = 0;
-                                            for submission in submissions.iter() {
-                                                let hashpower = MIN_HASHPOWER * 2u64.pow(submission.difficulty as u32 - MIN_DIFF);
-                                                total_hashpower += hashpower;
-                                            }
-
-                                            let _ = mine_success_sender.send(MessageInternalMineSuccess {
-                                                difficulty,
-                                                total_balance: balance,
-                                                pool_id: app_config.pool_id,
-                                                rewards,
-                                                total_hashpower,
-                                                submissions,
-                                            });
-                                            let new_challenge = InsertChallenge {
-                                                pool_id: app_config.pool_id,
-                                                challenge: latest_proof.challenge.to_vec(),
-                                                rewards_earned: None,
-                                            };
-
-                                            info!("NEW CHALLENGE: {:?}", new_challenge);
-                                            let result = app_database.add_new_challenge(new_challenge).await;
-
-                                            match result {
-                                                Ok(_) => {}
-                                                Err(AppDatabaseError::FailedToInsertNewEntity) => {
-                                                    panic!("Failed to create new challenge in database");
-                                                },
-                                                Err(_) => {
-                                                    panic!("AppDatabase query failed");
-                                                }
-                                            }
-                                            {
-                                                let mut prio_fee = app_prio_fee.lock().await;
-                                                let mut decrease_amount = 0;
-                                                if *prio_fee >=  1_000 {
-                                                    decrease_amount = 1_000;
-                                                }
-                                                if *prio_fee >=  50_000 {
-                                                    decrease_amount = 5_000;
-                                                }
-                                                if *prio_fee >=  100_000 {
-                                                    decrease_amount = 10_000;
-                                                }
-                                                
-                                                *prio_fee = prio_fee.saturating_sub(decrease_amount);
-                                            }
-                                            // reset nonce
-                                            {
-                                                let mut nonce = app_nonce.lock().await;
-                                                *nonce = 0;
-                                            }
-                                            break;
+                        let cu_limit_ix = ComputeBudgetInstruction::set_compute_unit_limit(cu_limit);
+                        let prio_fee_ix = ComputeBudgetInstruction::set_compute_unit_price(prio_fee);
+                        ixs.splice(0..0, [cu_limit_ix, prio_fee_ix]);
+
+                        let hash = if let Ok((hash, _slot)) = rpc_client.get_latest_blockhash_with_commitment(rpc_client.commitment()).await {
+                            hash
+                        } else {
+                            error!("Failed to get latest blockhash. retrying...");
+                            tokio::time::sleep(Duration::from_millis(1000)).await;
+                            continue;
+                        };
+
+                        let mut tx = Transaction::new_with_payer(&ixs, Some(&signer.pubkey()));
+                        tx.sign(&[&signer], hash);
+                        let sig = tx.signatures[0];
+                        info!("Sending signed tx {}...", sig);
+                        let mut pending_report = app_tx_manager.spawn(tx, rpc_client.commitment());
+
+                        // the proof usually updates before the transaction is reported confirmed
+                        let report = loop {
+                            tokio::select! {
+                                report = &mut pending_report => break Some(report),
+                                _ = tokio::time::sleep(Duration::from_millis(1000)) => {
+                                    if app_proof.lock().await.challenge != old_proof.challenge {
+                                        break None;
                                     }
                                 }
+                            }
+                        };
+
+                        match report {
+                            None => {
+                                info!("Proof updated before tx {} was reported, it landed.", sig);
+                                break Some((sig, prio_fee, compute_units));
+                            },
+                            Some(Ok(report)) => {
+                                info!("Mine tx {} {} after {} sends in {}ms", report.signature, report.outcome, report.sends, report.elapsed.as_millis());
+                                match report.outcome {
+                                    TxOutcome::Landed { .. } => break Some((sig, prio_fee, compute_units)),
+                                    TxOutcome::Expired => {
+                                        let mut prio_fee = app_prio_fee.lock().await;
+                                        if *prio_fee < 1_000_000 {
+                                            *prio_fee += 10_000;
+                                        }
+                                    },
+                                    // re-signing won't fix a transaction the program rejected
+                                    _ => break None,
+                                }
+                            },
+                            Some(Err(e)) => {
+                                error!("Mine tx task failed: {}", e);
+                                break None;
+                            }
+                        }
+                    };
 
-                                break;
+                    if let Some((sig, prio_fee, compute_units)) = landed {
+                        // success
+                        info!("Success!!");
+                        info!("Sig: {}", sig);
+                        let itxn = InsertTxn {
+                            txn_type: "mine".to_string(),
+                            signature: sig.to_string(),
+                            priority_fee: prio_fee as u32,
+                            compute_units: compute_units.map(|units| units.consumed),
+                            bus: Some(bus as u8),
+                        };
+                        let _ = app_database.add_new_txn(itxn).await.unwrap();
+                        // update proof
+                        loop {
+                            info!("Waiting for proof hash update");
+                            let latest_proof = {
+                                app_proof.lock().await.clone()
+                            };
+
+                            if old_proof.challenge.eq(&latest_proof.challenge) {
+                                info!("Proof challenge not updated yet..");
+                                old_proof = latest_proof;
+                                tokio::time::sleep(Duration::from_millis(1000)).await;
+                                continue;
                             } else {
-                                {
-                                    let mut prio_fee = app_prio_fee.lock().await;
-                                    if *prio_fee < 1_000_000 {
-                                        *prio_fee += 10_000;
+                                info!("Proof challenge updated! Checking rewards earned.");
+                                    let balance = (latest_proof.balance as f64) / 10f64.powf(ORE_TOKEN_DECIMALS as f64);
+                                    info!("New balance: {}", balance);
+                                    let rewards = latest_proof.balance - old_proof.balance;
+                                    let dec_rewards = (rewards as f64) / 10f64.powf(ORE_TOKEN_DECIMALS as f64);
+                                    info!("Earned: {} ORE", dec_rewards);
+
+                                    let submission_id = app_database.get_submission_id_with_nonce(u64::from_le_bytes(solution.n)).await.unwrap();
+
+                                    let _ = app_database.update_challenge_rewards(old_proof.challenge.to_vec(), submission_id, rewards).await.unwrap();
+                                    let _ = app_database.update_pool_rewards(app_wallet.pubkey().to_string(), rewards).await.unwrap();
+
+                                    let submissions = app_database.get_all_submission_for_challenge(old_proof.challenge.to_vec()).await.unwrap();
+
+                                    let mut total_hashpower: u64 = 0;
+                                    for submission in submissions.iter() {
+                                        let hashpower = MIN_HASHPOWER * 2u64.pow(submission.difficulty as u32 - MIN_DIFF);
+                                        total_hashpower += hashpower;
+                                    }
+
+                                    let _ = mine_success_sender.send(MessageInternalMineSuccess {
+                                        difficulty,
+                                        pool_balance: latest_proof.balance,
+                                        pool_id: app_config.pool_id,
+                                        rewards,
+                                        total_hashpower,
+                                        submissions,
+                                    });
+                                    let new_challenge = InsertChallenge {
+                                        pool_id: app_config.pool_id,
+                                        challenge: latest_proof.challenge.to_vec(),
+                                        rewards_earned: None,
+                                    };
+
+                                    info!("NEW CHALLENGE: {:?}", new_challenge);
+                                    let result = app_database.add_new_challenge(new_challenge).await;
+
+                                    match result {
+                                        Ok(_) => {}
+                                        Err(AppDatabaseError::FailedToInsertNewEntity) => {
+                                            panic!("Failed to create new challenge in database");
+                                        },
+                                        Err(_) => {
+                                            panic!("AppDatabase query failed");
+                                        }
+                                    }
+                                    {
+                                        let mut prio_fee = app_prio_fee.lock().await;
+                                        let mut decrease_amount = 0;
+                                        if *prio_fee >=  1_000 {
+                                            decrease_amount = 1_000;
+                                        }
+                                        if *prio_fee >=  50_000 {
+                                            decrease_amount = 5_000;
+                                        }
+                                        if *prio_fee >=  100_000 {
+                                            decrease_amount = 10_000;
+                                        }
+                                                
+                                        *prio_fee = prio_fee.saturating_sub(decrease_amount);
+                                    }
+                                    // reset nonce
+                                    {
+                                        let mut nonce = app_nonce.lock().await;
+                                        *nonce = 0;
                                     }
-                                }
-                                // sent error
-                                if i >= 2 {
-                                    info!("Failed to send after 3 attempts. Retrying after loading new config data.");
                                     break;
-                                }
                             }
-                            tokio::time::sleep(Duration::from_millis(500)).await;
-                        } else {
-                            error!("Failed to get latest blockhash. retrying...");
-                            tokio::time::sleep(Duration::from_millis(1000)).await;
                         }
-                    } 
+                    } else {
+                        info!("Mine tx didn't land. Retrying after loading new config data.");
+                        tokio::time::sleep(Duration::from_millis(1000)).await;
+                    }
 
                 } else {
                     error!("no best submission");
@@ -572,22 +1288,22 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let app_shared_state = shared_state.clone();
     let app_app_database = app_database.clone();
-    let app_rpc_client = rpc_client.clone();
+    let app_rpc_pool = rpc_pool.clone();
     let app_wallet = wallet_extension.clone();
+    let app_config = config.clone();
     tokio::spawn(async move {
         let app_database = app_app_database;
         loop {
             while let Some(msg) = mine_success_receiver.recv().await {
                 {
-                    let decimals = 10f64.powf(ORE_TOKEN_DECIMALS as f64);
-                    let pool_rewards_dec = (msg.rewards as f64).div(decimals);
-                    let shared_state = app_shared_state.read().await;
                     for submission in msg.submissions {
                         let hashpower = MIN_HASHPOWER * 2u64.pow(submission.difficulty as u32 - MIN_DIFF);
                         let hashpower_percent = (hashpower as u128).saturating_mul(1_000_000).saturating_div(msg.total_hashpower as u128);
 
                         //let decimals = 10f64.powf(ORE_TOKEN_DECIMALS as f64);
-                        let earned_rewards = hashpower_percent.saturating_mul(msg.rewards as u128).saturating_div(1_000_000) as u64;
+                        let gross_rewards = hashpower_percent.saturating_mul(msg.rewards as u128).saturating_div(1_000_000) as u64;
+                        let commission = (gross_rewards as u128).saturating_mul(app_config.pool_commission as u128).saturating_div(100) as u64;
+                        let earned_rewards = gross_rewards.saturating_sub(commission);
                         let _ = app_database.update_miner_reward(submission.miner_id, earned_rewards as u64).await.unwrap();
                         let earning = InsertEarning {
                             miner_id: submission.miner_id,
@@ -597,26 +1313,59 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         };
                         let _ = app_database.add_new_earning(earning).await.unwrap();
 
-                        if let Some((_socket_pubkey, socket_sender)) = shared_state.miner_sockets.get(&submission.miner_id) {
-                            let earned_rewards_dec = (earned_rewards as f64).div(decimals);
+                        // Referrers are paid out of the commission, never from the referee's share
+                        if app_config.referral_percent > 0 && commission > 0 {
+                            if let Ok(MinerReferral { referrer_id: Some(referrer_id), .. }) = app_database.get_miner_referral(submission.miner_id).await {
+                                let referral_rewards = ((earned_rewards as u128).saturating_mul(app_config.referral_percent as u128).saturating_div(100) as u64).min(commission);
+                                if referral_rewards > 0 {
+                                    let _ = app_database.update_miner_reward(referrer_id, referral_rewards).await;
+                                    let referral_earning = InsertReferralEarning {
+                                        referrer_id,
+                                        referee_id: submission.miner_id,
+                                        pool_id: msg.pool_id,
+                                        challenge_id: submission.challenge_id,
+                                        amount: referral_rewards,
+                                    };
+                                    if let Err(e) = app_database.add_new_referral_earning(referral_earning).await {
+                                        error!("Failed to add referral earning: {:?}", e);
+                                    }
+                                }
+                            }
+                        }
 
-                            let message = format!(
-                                "Submitted Difficulty: {}\nPool Earned: {} ORE.\nPool Balance: {}\nMiner Earned: {} ORE for difficulty: {}",
-                                msg.difficulty,
-                                pool_rewards_dec,
-                                msg.total_balance,
-                                earned_rewards_dec,
-                                submission.difficulty
-                            );
-                            if let Ok(_) = socket_sender.lock().await.send(Message::Text(message)).await {
-                            } else {
-                                println!("Failed to send client text");
+                        let worker_socket = {
+                            let shared_state = app_shared_state.read().await;
+                            shared_state.miner_sockets.get(&submission.miner_id)
+                                .and_then(|workers| workers.get(&submission.worker))
+                                .and_then(|addr| shared_state.sockets.get(addr).map(|connection| (*addr, connection.pubkey)))
+                        };
+                        if let Some((addr, miner_pubkey)) = worker_socket {
+                            let settled = ServerEvent::ChallengeSettled(ChallengeSettledEvent {
+                                pool_difficulty: msg.difficulty,
+                                pool_earned: msg.rewards,
+                                pool_balance: msg.pool_balance,
+                                miner_difficulty: submission.difficulty as u32,
+                                miner_earned: earned_rewards,
+                            });
+                            // looked up without holding the state lock
+                            let balance = app_database.get_miner_rewards(miner_pubkey.to_string()).await.ok();
+
+                            let shared_state = app_shared_state.read().await;
+                            if let Some(connection) = shared_state.sockets.get(&addr) {
+                                if !connection.send_event(&settled) {
+                                    println!("Failed to send client text");
+                                }
+                                if let Some(reward) = balance {
+                                    connection.send_event(&ServerEvent::BalanceUpdate(BalanceUpdateEvent {
+                                        balance: reward.balance,
+                                    }));
+                                }
                             }
                         }
 
                     }
                 }
-                if let Ok(balance) = app_rpc_client.get_balance(&app_wallet.pubkey()).await {
+                if let Ok(balance) = app_rpc_pool.client().get_balance(&app_wallet.pubkey()).await {
                     info!("Sol Balance: {:.2}", balance as f64 / LAMPORTS_PER_SOL as f64);
                 } else {
                     error!("Failed to load balance");
@@ -626,23 +1375,124 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
     
+    let (claim_settled_sender, mut claim_settled_receiver) = tokio::sync::mpsc::unbounded_channel::<MessageInternalClaimSettled>();
+
+    let app_app_database = app_database.clone();
+    let app_rpc_pool = rpc_pool.clone();
+    let app_wallet = wallet_extension.clone();
+    let app_config = config.clone();
+    let app_claim_settled_sender = claim_settled_sender.clone();
+    tokio::spawn(async move {
+        reconcile_pending_claims(app_app_database, app_rpc_pool, app_config.pool_id, app_wallet.pubkey(), app_claim_settled_sender).await;
+    });
+
+    // Notify miners when their claims settle
+    let app_shared_state = shared_state.clone();
+    tokio::spawn(async move {
+        while let Some(msg) = claim_settled_receiver.recv().await {
+            let shared_state = app_shared_state.read().await;
+            if let Some(workers) = shared_state.miner_sockets.get(&msg.miner_id) {
+                let event = ServerEvent::ClaimUpdate(ClaimUpdateEvent {
+                    claim_id: msg.claim_id,
+                    amount: msg.amount,
+                    state: msg.state.as_str().to_string(),
+                    signature: msg.signature.to_string(),
+                    error: msg.error,
+                });
+                for addr in workers.values() {
+                    if let Some(connection) = shared_state.sockets.get(addr) {
+                        if !connection.send_event(&event) {
+                            println!("Failed to send client text");
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    let last_payout_report = Arc::new(Mutex::new(None));
+    if args.auto_payout_interval > 0 {
+        let auto_payout_config = AutoPayoutConfig {
+            interval_secs: args.auto_payout_interval,
+            default_threshold: args.auto_payout_threshold,
+            max_payouts_per_run: args.auto_payout_max_per_run,
+            max_amount_per_run: args.auto_payout_max_amount_per_run,
+        };
+        let app_app_database = app_database.clone();
+        let app_rpc_pool = rpc_pool.clone();
+        let app_compute_unit_estimator = compute_unit_estimator.clone();
+        let app_tx_manager = tx_manager.clone();
+        let app_wallet = wallet_extension.clone();
+        let app_last_payout_report = last_payout_report.clone();
+        let app_config = config.clone();
+        let app_claim_settled_sender = claim_settled_sender.clone();
+        tokio::spawn(async move {
+            auto_payout_system(auto_payout_config, app_config.pool_id, app_app_database, app_rpc_pool, app_compute_unit_estimator, app_tx_manager, app_wallet, app_last_payout_report, app_claim_settled_sender).await;
+        });
+    }
+
+    if args.stake_percent > 0 {
+        let stake_config = StakeConfig {
+            interval_secs: args.stake_interval,
+            stake_percent: args.stake_percent,
+            reserve: args.stake_reserve,
+            min_stake_amount: args.stake_min_amount,
+        };
+        let app_app_database = app_database.clone();
+        let app_rpc_pool = rpc_pool.clone();
+        let app_compute_unit_estimator = compute_unit_estimator.clone();
+        let app_tx_manager = tx_manager.clone();
+        let app_wallet = wallet_extension.clone();
+        let app_proof = proof_ext.clone();
+        let app_config = config.clone();
+        tokio::spawn(async move {
+            auto_stake_system(stake_config, app_config.pool_id, app_app_database, app_rpc_pool, app_compute_unit_estimator, app_tx_manager, app_wallet, app_proof).await;
+        });
+    }
+
     let client_channel = client_message_sender.clone();
     let app_shared_state = shared_state.clone();
     let app = Router::new()
         .route("/", get(ws_handler))
+        .route("/ws/nonce", get(get_ws_nonce))
         .route("/latest-blockhash", get(get_latest_blockhash))
         .route("/pool/authority/pubkey", get(get_pool_authority_pubkey))
+        .route("/pool/stats", get(get_pool_stats))
         .route("/signup", post(post_signup))
+        .route("/signup/challenge", get(get_signup_challenge))
         .route("/claim", post(post_claim))
+        .route("/claim/status", get(get_claim_status))
         .route("/miner/rewards", get(get_miner_rewards))
         .route("/miner/balance", get(get_miner_balance))
+        .route("/miner/claims", get(get_miner_claims))
+        .route("/miner/referrals", get(get_miner_referrals).post(post_miner_referrals))
+        .route("/miner/workers", get(get_miner_workers))
+        .route("/miner/payout-address", get(get_miner_payout_address).post(post_miner_payout_address))
+        .route("/miner/payout-address/confirm", post(post_miner_payout_address_confirm))
+        .route("/miner/payout-threshold", post(post_miner_payout_threshold))
+        .route("/payouts/last-run", get(get_last_payout_report))
+        .route("/admin/whitelist", get(get_admin_whitelist).post(post_admin_whitelist).delete(delete_admin_whitelist))
+        .route("/admin/whitelist/import", post(post_admin_whitelist_import))
+        .route("/admin/miner/enable", post(post_admin_miner_enable))
+        .route("/admin/miner/disable", post(post_admin_miner_disable))
+        .route("/admin/miner/ban", post(post_admin_miner_ban))
+        .route("/admin/bans", get(get_admin_bans).delete(delete_admin_ban))
         .with_state(app_shared_state)
+        .layer(middleware::from_fn_with_state(rate_limiter.clone(), rate_limit_middleware))
         .layer(Extension(app_database))
         .layer(Extension(config))
         .layer(Extension(wallet_extension))
         .layer(Extension(client_channel))
-        .layer(Extension(rpc_client))
-        .layer(Extension(client_nonce_ranges))
+        .layer(Extension(rpc_pool))
+        .layer(Extension(compute_unit_estimator))
+        .layer(Extension(tx_manager))
+        .layer(Extension(signup_challenges))
+        .layer(Extension(used_signatures))
+        .layer(Extension(ws_auth))
+        .layer(Extension(rate_limiter))
+        .layer(Extension(last_payout_report))
+        .layer(Extension(claim_settled_sender))
+        .layer(Extension(proof_ext))
         // Logging
         .layer(
             TraceLayer::new_for_http()
@@ -650,22 +1500,45 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         );
 
 
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000")
-        .await
-        .unwrap();
-
-    tracing::debug!("listening on {}", listener.local_addr().unwrap());
-
     let app_shared_state = shared_state.clone();
+    let ws_idle_timeout = Duration::from_secs(args.ws_idle_timeout);
     tokio::spawn(async move {
-        ping_check_system(&app_shared_state).await;
+        ping_check_system(&app_shared_state, ws_idle_timeout).await;
     });
     
-    axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>()
-    ).await
-    .unwrap();
+    let addr = SocketAddr::from(([0, 0, 0, 0], 3000));
+    if let (Some(tls_cert), Some(tls_key)) = (args.tls_cert, args.tls_key) {
+        let tls_config = match RustlsConfig::from_pem_file(&tls_cert, &tls_key).await {
+            Ok(tls_config) => tls_config,
+            Err(e) => {
+                return Err(format!("Failed to load TLS certificate: {}", e).into());
+            }
+        };
+
+        let app_tls_config = tls_config.clone();
+        let tls_reload_interval = Duration::from_secs(args.tls_reload_interval);
+        tokio::spawn(async move {
+            tls_reload_system(app_tls_config, tls_cert.into(), tls_key.into(), tls_reload_interval).await;
+        });
+
+        tracing::debug!("listening on {} with TLS", addr);
+        axum_server::bind_rustls(addr, tls_config)
+            .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .unwrap();
+    } else {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .unwrap();
+
+        tracing::debug!("listening on {}", listener.local_addr().unwrap());
+
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>()
+        ).await
+        .unwrap();
+    }
 
     Ok(())
 }
@@ -680,11 +1553,46 @@ async fn get_pool_authority_pubkey(
         .unwrap()
 }
 
-async fn get_latest_blockhash(
-    Extension(rpc_client): Extension<Arc<RpcClient>>,
+async fn get_pool_stats(
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(wallet): Extension<Arc<Keypair>>,
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(proof): Extension<Arc<Mutex<Proof>>>,
 ) -> impl IntoResponse {
+    let db_pool = app_database.get_pool_by_authority_pubkey(wallet.pubkey().to_string()).await;
+    let outstanding_rewards = app_database.get_pool_outstanding_rewards(app_config.pool_id).await;
+
+    if let (Ok(db_pool), Ok(outstanding_rewards)) = (db_pool, outstanding_rewards) {
+        let proof_balance = {
+            proof.lock().await.balance
+        };
+        let decimals = 10f64.powf(ORE_TOKEN_DECIMALS as f64);
+        let response = serde_json::json!({
+            "total_rewards": db_pool.total_rewards,
+            "claimed_rewards": db_pool.claimed_rewards,
+            "outstanding_rewards": outstanding_rewards.balance,
+            "total_staked": db_pool.total_staked,
+            "total_staked_dec": (db_pool.total_staked as f64).div(decimals),
+            "proof_balance": proof_balance,
+            "proof_balance_dec": (proof_balance as f64).div(decimals),
+        });
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(response.to_string())
+            .unwrap();
+    } else {
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body("Failed to get pool stats".to_string())
+            .unwrap();
+    }
+}
 
-    let latest_blockhash = rpc_client.get_latest_blockhash().await.unwrap();
+async fn get_latest_blockhash(
+    Extension(rpc_pool): Extension<Arc<RpcPool>>,
+) -> impl IntoResponse {
+    let latest_blockhash = rpc_pool.client().get_latest_blockhash().await.unwrap();
 
     let serialized_blockhash = bincode::serialize(&latest_blockhash).unwrap();
 
@@ -699,17 +1607,31 @@ async fn get_latest_blockhash(
 #[derive(Deserialize)]
 struct SignupParams {
     pubkey: String,
+    referral_code: Option<String>,
+    // id returned with the signup challenge, required in signature mode
+    challenge_id: Option<u64>,
 }
 
 async fn post_signup(
     query_params: Query<SignupParams>,
     Extension(app_database): Extension<Arc<AppDatabase>>,
-    Extension(rpc_client): Extension<Arc<RpcClient>>,
+    Extension(rpc_pool): Extension<Arc<RpcPool>>,
+    Extension(tx_manager): Extension<Arc<TxManager>>,
     Extension(wallet): Extension<Arc<Keypair>>,
     Extension(app_config): Extension<Arc<Config>>,
+    Extension(signup_challenges): Extension<Arc<Mutex<SignupChallenges>>>,
+    Extension(rate_limiter): Extension<Arc<Mutex<RateLimiter>>>,
     body: String,
 ) -> impl IntoResponse {
+    let rpc_client = rpc_pool.client();
     if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
+        if rate_limiter.lock().await.check_pubkey(user_pubkey).is_err() {
+            return Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .body("Too many requests for this pubkey".to_string())
+                .unwrap();
+        }
+
         let db_miner = app_database.get_miner_by_pubkey_str(user_pubkey.to_string()).await;
 
         match db_miner {
@@ -721,6 +1643,12 @@ async fn post_signup(
                         .header("Content-Type", "text/text")
                         .body("EXISTS".to_string())
                         .unwrap();
+                } else {
+                    // Disabled accounts can only be re-enabled by an admin
+                    return Response::builder()
+                        .status(StatusCode::FORBIDDEN)
+                        .body("Miner account is disabled".to_string())
+                        .unwrap();
                 }
             }
             Err(AppDatabaseError::EntityDoesNotExist) => {
@@ -735,136 +1663,139 @@ async fn post_signup(
             }
         }
 
-        if let Some(whitelist) = &app_config.whitelist {
-            if whitelist.contains(&user_pubkey) {
-                let result = app_database.add_new_miner(user_pubkey.to_string(), true).await;
-                let miner = app_database.get_miner_by_pubkey_str(user_pubkey.to_string()).await.unwrap();
-
-                let wallet_pubkey = wallet.pubkey();
-                let pool = app_database.get_pool_by_authority_pubkey(wallet_pubkey.to_string()).await.unwrap();
-
-                if result.is_ok() {
+        let referrer_id = if let Some(referral_code) = &query_params.referral_code {
+            if let Ok(referrer) = app_database.get_miner_by_referral_code(referral_code.clone()).await {
+                Some(referrer.id)
+            } else {
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body("Invalid referral code".to_string())
+                    .unwrap();
+            }
+        } else {
+            None
+        };
 
-                    let new_reward = InsertReward {
-                        miner_id: miner.id,
-                        pool_id: pool.id,
-                    };
-                    let result = app_database.add_new_reward(new_reward).await;
-
-                    if result.is_ok() {
-                        return Response::builder()
-                            .status(StatusCode::OK)
-                            .header("Content-Type", "text/text")
-                            .body("SUCCESS".to_string())
-                            .unwrap();
-                    } else {
-                        error!("Failed to add miner rewards tracker to database");
-                        return Response::builder()
-                            .status(StatusCode::INTERNAL_SERVER_ERROR)
-                            .body("Failed to add miner rewards tracker to database".to_string())
-                            .unwrap();
-                    }
-                } else {
-                    error!("Failed to add miner to database");
-                    return Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .body("Failed to add miner to database".to_string())
-                        .unwrap();
-                }
-            } 
+        if let Ok(true) = app_database.is_whitelisted(user_pubkey.to_string()).await {
+            return add_signed_up_miner(&app_database, app_config.pool_id, &user_pubkey, referrer_id).await;
         }
 
+        if app_config.signup_mode == SignupMode::Signature {
+            // Challenges are single use, a failed attempt needs a new one
+            let challenge = match query_params.challenge_id {
+                Some(challenge_id) => signup_challenges.lock().await.take(challenge_id, &user_pubkey),
+                None => None,
+            };
+            let challenge = if let Some(challenge) = challenge {
+                challenge
+            } else {
+                let e = SignupProofError::MissingChallenge;
+                return Response::builder()
+                    .status(e.status())
+                    .header("Content-Type", "application/json")
+                    .body(e.to_json())
+                    .unwrap();
+            };
 
+            if let Err(e) = validate_signup_proof(
+                &body,
+                &challenge,
+                Duration::from_secs(SIGNUP_CHALLENGE_MAX_AGE),
+                &wallet.pubkey(),
+                &user_pubkey,
+                app_config.signup_pow_difficulty,
+            ) {
+                error!("Invalid signup proof: {}", e.message());
+                return Response::builder()
+                    .status(e.status())
+                    .header("Content-Type", "application/json")
+                    .body(e.to_json())
+                    .unwrap();
+            }
 
-        let serialized_tx =  BASE64_STANDARD.decode(body.clone()).unwrap();
-        let tx: Transaction = if let Ok(tx) = bincode::deserialize(&serialized_tx) {
-            tx
-        } else {
-            error!("Failed to deserialize tx");
-            return Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .body("Invalid Tx".to_string())
-                .unwrap();
-
-        };
-
-        if !tx.is_signed() {
-            error!("Tx missing signer");
-            return Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .body("Invalid Tx".to_string())
-                .unwrap();
+            return add_signed_up_miner(&app_database, app_config.pool_id, &user_pubkey, referrer_id).await;
         }
 
-        let ixs = tx.message.instructions.clone();
+        if app_config.signup_mode == SignupMode::OnChain {
+            let signature = match decode_signup_signature(&body) {
+                Ok(signature) => signature,
+                Err(e) => {
+                    return Response::builder()
+                        .status(e.status())
+                        .header("Content-Type", "application/json")
+                        .body(e.to_json())
+                        .unwrap();
+                }
+            };
 
-        if ixs.len() > 1 {
-            error!("Too many instructions");
-            return Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .body("Invalid Tx".to_string())
-                .unwrap();
-        }
+            if let Err(e) = verify_onchain_signup_payment(&rpc_client, &signature, &user_pubkey, &wallet.pubkey(), app_config.signup_cost).await {
+                error!("Invalid signup payment {}: {}", signature, e.message());
+                return Response::builder()
+                    .status(e.status())
+                    .header("Content-Type", "application/json")
+                    .body(e.to_json())
+                    .unwrap();
+            }
 
-        let base_ix = system_instruction::transfer(&user_pubkey, &wallet.pubkey(), 1_000_000);
-        let mut accts = Vec::new();
-        for account_index in ixs[0].accounts.clone() {
-            accts.push(tx.key(0, account_index.into()));
-        }
+            // The unique signature column stops one payment from enabling several miners
+            let payment = InsertSignupPayment {
+                miner_pubkey: user_pubkey.to_string(),
+                signature: signature.to_string(),
+                amount: app_config.signup_cost,
+            };
+            match app_database.add_signup_payment(payment).await {
+                Ok(_) => {},
+                Err(AppDatabaseError::EntityAlreadyExists) => {
+                    let e = SignupPaymentError::SignatureAlreadyUsed;
+                    return Response::builder()
+                        .status(e.status())
+                        .header("Content-Type", "application/json")
+                        .body(e.to_json())
+                        .unwrap();
+                },
+                Err(_) => {
+                    return Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body("Failed to record signup payment".to_string())
+                        .unwrap();
+                }
+            }
 
-        if accts.len() != 2 {
-            error!("too many accts");
-            return Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .body("Invalid Tx".to_string())
-                .unwrap();
+            let res = add_signed_up_miner(&app_database, app_config.pool_id, &user_pubkey, referrer_id).await;
+            if res.status() != StatusCode::OK {
+                // Let the user retry with the same payment
+                let _ = app_database.remove_signup_payment(signature.to_string()).await;
+            }
+            return res;
         }
 
-        if ixs[0].data.ne(&base_ix.data) {
-            error!("data missmatch");
+        let tx = match decode_signup_transaction(&body) {
+            Ok(tx) => tx,
+            Err(e) => {
+                error!("Invalid signup tx: {}", e.message());
+                return Response::builder()
+                    .status(e.status())
+                    .header("Content-Type", "application/json")
+                    .body(e.to_json())
+                    .unwrap();
+            }
+        };
+
+        if let Err(e) = validate_signup_payment(&rpc_client, &tx, &user_pubkey, &wallet.pubkey(), app_config.signup_cost).await {
+            error!("Invalid signup tx: {}", e.message());
             return Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .body("Invalid Tx".to_string())
+                .status(e.status())
+                .header("Content-Type", "application/json")
+                .body(e.to_json())
                 .unwrap();
         } else {
             println!("Valid signup tx, submitting.");
 
-            if let Ok(_sig) = rpc_client.send_and_confirm_transaction(&tx).await {
-                let res = app_database.add_new_miner(user_pubkey.to_string(), true).await;
-                let miner = app_database.get_miner_by_pubkey_str(user_pubkey.to_string()).await.unwrap();
-
-                let wallet_pubkey = wallet.pubkey();
-                let pool = app_database.get_pool_by_authority_pubkey(wallet_pubkey.to_string()).await.unwrap();
-
-                if res.is_ok() {
-                    let new_reward = InsertReward {
-                        miner_id: miner.id,
-                        pool_id: pool.id,
-                    };
-                    let result = app_database.add_new_reward(new_reward).await;
-
-                    if result.is_ok() {
-                        return Response::builder()
-                            .status(StatusCode::OK)
-                            .header("Content-Type", "text/text")
-                            .body("SUCCESS".to_string())
-                            .unwrap();
-                    } else {
-                        error!("Failed to add miner rewards tracker to database");
-                        return Response::builder()
-                            .status(StatusCode::INTERNAL_SERVER_ERROR)
-                            .body("Failed to add miner rewards tracker to database".to_string())
-                            .unwrap();
-                    }
-                } else {
-                    error!("Failed to add miner to database");
-                    return Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .body("Failed to add user to database".to_string())
-                        .unwrap();
-                }
-
+            let report = tx_manager.send(&tx, rpc_client.commitment()).await;
+            if report.landed() {
+                return add_signed_up_miner(&app_database, app_config.pool_id, &user_pubkey, referrer_id).await;
             } else {
+                error!("Signup tx {} {}", report.signature, report.outcome);
                 return Response::builder()
                     .status(StatusCode::INTERNAL_SERVER_ERROR)
                     .body("Failed to send tx".to_string())
@@ -881,6 +1812,86 @@ async fn post_signup(
     }
 }
 
+async fn add_signed_up_miner(app_database: &AppDatabase, pool_id: i32, user_pubkey: &Pubkey, referrer_id: Option<i32>) -> Response<String> {
+    if app_database.add_new_miner(user_pubkey.to_string(), true).await.is_err() {
+        error!("Failed to add miner to database");
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body("Failed to add user to database".to_string())
+            .unwrap();
+    }
+
+    let miner = app_database.get_miner_by_pubkey_str(user_pubkey.to_string()).await.unwrap();
+    if let Some(referrer_id) = referrer_id {
+        if app_database.update_miner_referrer(miner.id, referrer_id).await.is_err() {
+            error!("Failed to set referrer for miner {}", miner.id);
+        }
+    }
+
+    let new_reward = InsertReward {
+        miner_id: miner.id,
+        pool_id,
+    };
+    if app_database.add_new_reward(new_reward).await.is_err() {
+        error!("Failed to add miner rewards tracker to database");
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body("Failed to add miner rewards tracker to database".to_string())
+            .unwrap();
+    }
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "text/text")
+        .body("SUCCESS".to_string())
+        .unwrap()
+}
+
+async fn get_signup_challenge(
+    query_params: Query<SignupParams>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Extension(wallet): Extension<Arc<Keypair>>,
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(signup_challenges): Extension<Arc<Mutex<SignupChallenges>>>,
+    Extension(rate_limiter): Extension<Arc<Mutex<RateLimiter>>>,
+) -> impl IntoResponse {
+    if app_config.signup_mode != SignupMode::Signature {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(format!("Pool signup mode is {}", app_config.signup_mode.as_str()))
+            .unwrap();
+    }
+
+    let user_pubkey = if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
+        user_pubkey
+    } else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid Pubkey".to_string())
+            .unwrap();
+    };
+
+    let max_age = Duration::from_secs(SIGNUP_CHALLENGE_MAX_AGE);
+    let ip = rate_limiter.lock().await.client_ip(&addr, &headers);
+    let (challenge_id, challenge) = signup_challenges.lock().await.issue(user_pubkey, ip, max_age);
+
+    let msg = signup_challenge_message(&wallet.pubkey(), &user_pubkey, &challenge);
+    let res = serde_json::json!({
+        "challenge_id": challenge_id,
+        "challenge": BASE64_STANDARD.encode(challenge),
+        "message": BASE64_STANDARD.encode(msg),
+        "pow_difficulty": app_config.signup_pow_difficulty,
+        "expires_in": SIGNUP_CHALLENGE_MAX_AGE,
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(res.to_string())
+        .unwrap()
+}
+
 #[derive(Deserialize)]
 struct PubkeyParam {
     pubkey: String,
@@ -914,245 +1925,1381 @@ async fn get_miner_rewards(
             .status(StatusCode::BAD_REQUEST)
             .body("Invalid public key".to_string())
             .unwrap();
-    }
-}
+    }
+}
+
+async fn get_miner_balance(
+    query_params: Query<PubkeyParam>,
+    Extension(rpc_pool): Extension<Arc<RpcPool>>,
+) -> impl IntoResponse {
+    let rpc_client = rpc_pool.client();
+    if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
+        let miner_token_account = get_associated_token_address(&user_pubkey, &get_ore_mint());
+        if let Ok(response) = rpc_client.get_token_account_balance(&miner_token_account).await {
+            return Response::builder()
+                .status(StatusCode::OK)
+                .body(response.ui_amount_string)
+                .unwrap();
+        } else {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body("Failed to get token account balance".to_string())
+                .unwrap();
+        }
+    } else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid public key".to_string())
+            .unwrap();
+    }
+}
+
+#[derive(Deserialize)]
+struct MinerClaimsParams {
+    pubkey: String,
+    page: Option<u64>,
+    limit: Option<u64>,
+}
+
+async fn get_miner_claims(
+    query_params: Query<MinerClaimsParams>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+) -> impl IntoResponse {
+    if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
+        let page = query_params.page.unwrap_or(0);
+        let limit = query_params.limit.unwrap_or(25).clamp(1, MAX_CLAIMS_PAGE_SIZE);
+
+        match app_database.get_miner_claims(user_pubkey.to_string(), limit, page.saturating_mul(limit)).await {
+            Ok(claims) => {
+                let decimals = 10f64.powf(ORE_TOKEN_DECIMALS as f64);
+                let claims: Vec<serde_json::Value> = claims.into_iter().map(|claim| {
+                    serde_json::json!({
+                        "id": claim.id,
+                        "amount": claim.amount,
+                        "amount_dec": (claim.amount as f64).div(decimals),
+                        "status": claim.status,
+                        "signature": claim.signature,
+                        "priority_fee": claim.priority_fee,
+                        "created_at": claim.created_at,
+                    })
+                }).collect();
+                let response = serde_json::json!({
+                    "page": page,
+                    "limit": limit,
+                    "claims": claims,
+                });
+                return Response::builder()
+                    .status(StatusCode::OK)
+                    .header("Content-Type", "application/json")
+                    .body(response.to_string())
+                    .unwrap();
+            },
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body("Failed to get claims".to_string())
+                    .unwrap();
+            }
+        }
+    } else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid public key".to_string())
+            .unwrap();
+    }
+}
+
+fn generate_referral_code() -> String {
+    rand::thread_rng()
+        .sample_iter(&rand::distributions::Alphanumeric)
+        .take(REFERRAL_CODE_LEN)
+        .map(char::from)
+        .collect()
+}
+
+async fn get_miner_referrals(
+    query_params: Query<PubkeyParam>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+) -> impl IntoResponse {
+    let user_pubkey = if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
+        user_pubkey
+    } else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid public key".to_string())
+            .unwrap();
+    };
+
+    let miner = if let Ok(miner) = app_database.get_miner_by_pubkey_str(user_pubkey.to_string()).await {
+        miner
+    } else {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body("Miner not found".to_string())
+            .unwrap();
+    };
+
+    let referral = if let Ok(referral) = app_database.get_miner_referral(miner.id).await {
+        referral
+    } else {
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body("Failed to get referral info".to_string())
+            .unwrap();
+    };
+
+    // null until the miner creates a code
+    if let Ok(stats) = app_database.get_referral_stats(miner.id).await {
+        let res = serde_json::json!({
+            "referral_code": referral.referral_code,
+            "referee_count": stats.referee_count,
+            "total_earned": stats.total_earned,
+        });
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(res.to_string())
+            .unwrap();
+    } else {
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body("Failed to get referral stats".to_string())
+            .unwrap();
+    }
+}
+
+#[derive(Deserialize)]
+struct SignedTimestampParams {
+    timestamp: u64,
+}
+
+async fn post_miner_referrals(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Basic>>,
+    query_params: Query<SignedTimestampParams>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(used_signatures): Extension<Arc<Mutex<UsedSignatures>>>,
+) -> impl IntoResponse {
+    let msg = signed_request_message(SIGNED_ACTION_REFERRAL_CODE, &[], query_params.timestamp);
+    let user_pubkey = match verify_signed_request(&auth_header, query_params.timestamp, &msg, &used_signatures).await {
+        Ok(user_pubkey) => user_pubkey,
+        Err((status, reason)) => {
+            return Response::builder()
+                .status(status)
+                .body(reason.to_string())
+                .unwrap();
+        }
+    };
+
+    let miner = match app_database.get_miner_by_pubkey_str(user_pubkey.to_string()).await {
+        Ok(miner) => miner,
+        Err(AppDatabaseError::EntityDoesNotExist) => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body("Miner not found".to_string())
+                .unwrap();
+        },
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body("Failed to get miner".to_string())
+                .unwrap();
+        }
+    };
+
+    let mut referral = if let Ok(referral) = app_database.get_miner_referral(miner.id).await {
+        referral
+    } else {
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body("Failed to get referral info".to_string())
+            .unwrap();
+    };
+
+    // An existing code is returned as is, otherwise retry on the rare collision
+    let mut attempts = 0;
+    while referral.referral_code.is_none() && attempts < 3 {
+        attempts += 1;
+        let _ = app_database.update_miner_referral_code(miner.id, generate_referral_code()).await;
+        if let Ok(updated) = app_database.get_miner_referral(miner.id).await {
+            referral = updated;
+        }
+    }
+
+    if let Some(referral_code) = referral.referral_code {
+        let res = serde_json::json!({
+            "referral_code": referral_code,
+        });
+        return Response::builder()
+            .status(StatusCode::OK)
+            .header("Content-Type", "application/json")
+            .body(res.to_string())
+            .unwrap();
+    } else {
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body("Failed to create referral code".to_string())
+            .unwrap();
+    }
+}
+
+// domain is SIGNED_REQUEST_DOMAIN
+// action is 1 u8
+// payload is the action's arguments
+// timestamp is 8 u8
+fn signed_request_message(action: u8, payload: &[u8], timestamp: u64) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(SIGNED_REQUEST_DOMAIN.len() + 1 + payload.len() + 8);
+    msg.extend_from_slice(SIGNED_REQUEST_DOMAIN);
+    msg.push(action);
+    msg.extend_from_slice(payload);
+    msg.extend_from_slice(&timestamp.to_le_bytes());
+    msg
+}
+
+/// Signatures accepted by `verify_signed_request`, kept until their timestamp can no
+/// longer pass so a captured request can't be replayed.
+#[derive(Default)]
+struct UsedSignatures {
+    signatures: HashMap<Signature, Instant>,
+}
+
+impl UsedSignatures {
+    /// Records `signature`, false if it was already used.
+    fn insert(&mut self, signature: Signature) -> bool {
+        // a timestamp is accepted from SIGNED_REQUEST_MAX_AGE in the past to as far in the future
+        let max_age = Duration::from_secs(2 * SIGNED_REQUEST_MAX_AGE);
+        self.signatures.retain(|_, used_at| used_at.elapsed() <= max_age);
+        if self.signatures.contains_key(&signature) {
+            return false;
+        }
+        self.signatures.insert(signature, Instant::now());
+        true
+    }
+}
+
+/// Verifies a Basic auth header where the username is the miner pubkey and the
+/// password is that key's signature over `msg`. Each signature is only accepted once.
+async fn verify_signed_request(auth_header: &axum_extra::headers::Authorization<Basic>, timestamp: u64, msg: &[u8], used_signatures: &Mutex<UsedSignatures>) -> Result<Pubkey, (StatusCode, &'static str)> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
+    if now.abs_diff(timestamp) > SIGNED_REQUEST_MAX_AGE {
+        return Err((StatusCode::UNAUTHORIZED, "Timestamp too old."));
+    }
+
+    let user_pubkey = if let Ok(user_pubkey) = Pubkey::from_str(auth_header.username()) {
+        user_pubkey
+    } else {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid pubkey"));
+    };
+
+    if let Ok(signature) = Signature::from_str(auth_header.password()) {
+        if signature.verify(&user_pubkey.to_bytes(), msg) {
+            if !used_signatures.lock().await.insert(signature) {
+                return Err((StatusCode::UNAUTHORIZED, "Signature already used"));
+            }
+            return Ok(user_pubkey);
+        } else {
+            return Err((StatusCode::UNAUTHORIZED, "Sig verification failed"));
+        }
+    } else {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid signature"));
+    }
+}
+
+/// Per worker submission stats over the last day, along with which workers are
+/// connected right now and their round trip time.
+async fn get_miner_workers(
+    query_params: Query<PubkeyParam>,
+    State(app_state): State<Arc<RwLock<AppState>>>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+) -> impl IntoResponse {
+    let user_pubkey = if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
+        user_pubkey
+    } else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid public key".to_string())
+            .unwrap();
+    };
+
+    let miner = if let Ok(miner) = app_database.get_miner_by_pubkey_str(user_pubkey.to_string()).await {
+        miner
+    } else {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body("Miner not found".to_string())
+            .unwrap();
+    };
+
+    let stats = if let Ok(stats) = app_database.get_worker_stats(miner.id, WORKER_STATS_WINDOW).await {
+        stats
+    } else {
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body("Failed to get worker stats".to_string())
+            .unwrap();
+    };
+
+    // connected workers and their last measured round trip time
+    let mut online: HashMap<String, Option<u64>> = {
+        let app_state = app_state.read().await;
+        app_state.miner_sockets.get(&miner.id)
+            .map(|workers| workers.iter()
+                .map(|(worker, addr)| (worker.clone(), app_state.sockets.get(addr).and_then(|c| c.health.rtt_ms())))
+                .collect())
+            .unwrap_or_default()
+    };
+
+    let mut workers = Vec::new();
+    for stat in stats {
+        let rtt_ms = online.remove(&stat.worker);
+        workers.push(serde_json::json!({
+            "worker": stat.worker,
+            "online": rtt_ms.is_some(),
+            "rtt_ms": rtt_ms.flatten(),
+            "submissions": stat.submissions,
+            "best_difficulty": stat.best_difficulty,
+            "last_submission_at": stat.last_submission_at,
+        }));
+    }
+    // connected workers without any submissions in the window yet
+    for (worker, rtt_ms) in online {
+        workers.push(serde_json::json!({
+            "worker": worker,
+            "online": true,
+            "rtt_ms": rtt_ms,
+            "submissions": 0,
+            "best_difficulty": null,
+            "last_submission_at": null,
+        }));
+    }
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(serde_json::Value::Array(workers).to_string())
+        .unwrap()
+}
+
+async fn get_miner_payout_address(
+    query_params: Query<PubkeyParam>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+) -> impl IntoResponse {
+    if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
+        match app_database.get_miner_payout_address(user_pubkey.to_string()).await {
+            Ok(payout_address) => {
+                let response = serde_json::json!({
+                    "payout_pubkey": payout_address.payout_pubkey.unwrap_or(user_pubkey.to_string()),
+                    "pending_payout_pubkey": payout_address.pending_payout_pubkey,
+                    "pending_payout_available_at": payout_address.pending_payout_available_at,
+                });
+                return Response::builder()
+                    .status(StatusCode::OK)
+                    .header("Content-Type", "application/json")
+                    .body(response.to_string())
+                    .unwrap();
+            },
+            Err(AppDatabaseError::EntityDoesNotExist) => {
+                return Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body("Miner not found".to_string())
+                    .unwrap();
+            },
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body("Failed to get payout address".to_string())
+                    .unwrap();
+            }
+        }
+    } else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid public key".to_string())
+            .unwrap();
+    }
+}
+
+#[derive(Deserialize)]
+struct PayoutAddressParams {
+    payout_pubkey: String,
+    timestamp: u64,
+}
+
+async fn post_miner_payout_address(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Basic>>,
+    query_params: Query<PayoutAddressParams>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(used_signatures): Extension<Arc<Mutex<UsedSignatures>>>,
+) -> impl IntoResponse {
+    let payout_pubkey = if let Ok(payout_pubkey) = Pubkey::from_str(&query_params.payout_pubkey) {
+        payout_pubkey
+    } else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid payout pubkey".to_string())
+            .unwrap();
+    };
+
+    // payout pubkey is 32 u8
+    let msg = signed_request_message(SIGNED_ACTION_SET_PAYOUT_ADDRESS, &payout_pubkey.to_bytes(), query_params.timestamp);
+    let user_pubkey = match verify_signed_request(&auth_header, query_params.timestamp, &msg, &used_signatures).await {
+        Ok(user_pubkey) => user_pubkey,
+        Err((status, reason)) => {
+            return Response::builder()
+                .status(status)
+                .body(reason.to_string())
+                .unwrap();
+        }
+    };
+
+    let payout_address = match app_database.get_miner_payout_address(user_pubkey.to_string()).await {
+        Ok(payout_address) => payout_address,
+        Err(AppDatabaseError::EntityDoesNotExist) => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body("Miner not found".to_string())
+                .unwrap();
+        },
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body("Failed to get payout address".to_string())
+                .unwrap();
+        }
+    };
+
+    if payout_address.payout_pubkey == Some(payout_pubkey.to_string()) {
+        return Response::builder()
+            .status(StatusCode::OK)
+            .body("UNCHANGED".to_string())
+            .unwrap();
+    }
+
+    // Every payout address, the first one included, is time locked so a leaked
+    // mining key can't immediately redirect claims. Until it's confirmed claims
+    // keep going to the mining key itself.
+    if app_database.update_miner_pending_payout_address(payout_address.id, payout_pubkey.to_string(), app_config.payout_address_timelock).await.is_ok() {
+        info!("Miner {} requested payout address change to {}", user_pubkey, payout_pubkey);
+        return Response::builder()
+            .status(StatusCode::ACCEPTED)
+            .body("PENDING".to_string())
+            .unwrap();
+    }
+
+    error!("Failed to update miner payout address");
+    return Response::builder()
+        .status(StatusCode::INTERNAL_SERVER_ERROR)
+        .body("Failed to update payout address".to_string())
+        .unwrap();
+}
+
+async fn post_miner_payout_address_confirm(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Basic>>,
+    query_params: Query<PayoutAddressParams>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(used_signatures): Extension<Arc<Mutex<UsedSignatures>>>,
+) -> impl IntoResponse {
+    let payout_pubkey = if let Ok(payout_pubkey) = Pubkey::from_str(&query_params.payout_pubkey) {
+        payout_pubkey
+    } else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid payout pubkey".to_string())
+            .unwrap();
+    };
+
+    // payout pubkey is 32 u8
+    let msg = signed_request_message(SIGNED_ACTION_CONFIRM_PAYOUT_ADDRESS, &payout_pubkey.to_bytes(), query_params.timestamp);
+    let user_pubkey = match verify_signed_request(&auth_header, query_params.timestamp, &msg, &used_signatures).await {
+        Ok(user_pubkey) => user_pubkey,
+        Err((status, reason)) => {
+            return Response::builder()
+                .status(status)
+                .body(reason.to_string())
+                .unwrap();
+        }
+    };
+
+    let payout_address = match app_database.get_miner_payout_address(user_pubkey.to_string()).await {
+        Ok(payout_address) => payout_address,
+        Err(AppDatabaseError::EntityDoesNotExist) => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body("Miner not found".to_string())
+                .unwrap();
+        },
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body("Failed to get payout address".to_string())
+                .unwrap();
+        }
+    };
+
+    if payout_address.pending_payout_pubkey != Some(payout_pubkey.to_string()) {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("No matching pending payout address change".to_string())
+            .unwrap();
+    }
+
+    match app_database.confirm_miner_pending_payout_address(payout_address.id, payout_pubkey.to_string()).await {
+        Ok(_) => {
+            info!("Miner {} confirmed payout address {}", user_pubkey, payout_pubkey);
+            return Response::builder()
+                .status(StatusCode::OK)
+                .body("SUCCESS".to_string())
+                .unwrap();
+        },
+        Err(AppDatabaseError::FailedToUpdateEntity) => {
+            return Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body("Payout address change is still time locked".to_string())
+                .unwrap();
+        },
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body("Failed to confirm payout address".to_string())
+                .unwrap();
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ClaimParams {
+    pubkey: String,
+    amount: u64,
+}
+
+async fn post_claim(
+    query_params: Query<ClaimParams>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(rpc_pool): Extension<Arc<RpcPool>>,
+    Extension(compute_unit_estimator): Extension<Arc<ComputeUnitEstimator>>,
+    Extension(tx_manager): Extension<Arc<TxManager>>,
+    Extension(wallet): Extension<Arc<Keypair>>,
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(claim_settled_sender): Extension<UnboundedSender<MessageInternalClaimSettled>>,
+    Extension(rate_limiter): Extension<Arc<Mutex<RateLimiter>>>,
+) -> impl IntoResponse {
+    if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
+        if rate_limiter.lock().await.check_pubkey(user_pubkey).is_err() {
+            return Response::builder()
+                .status(StatusCode::TOO_MANY_REQUESTS)
+                .body("Too many requests for this pubkey".to_string())
+                .unwrap();
+        }
+
+        let amount = query_params.amount;
+        if let Ok(miner_rewards) = app_database.get_miner_rewards(user_pubkey.to_string()).await {
+            if amount > miner_rewards.balance {
+                return Response::builder()
+                    .status(StatusCode::BAD_REQUEST)
+                    .body("claim amount exceeds miner rewards balance".to_string())
+                    .unwrap();
+            }
+
+            let miner = app_database.get_miner_by_pubkey_str(user_pubkey.to_string()).await.unwrap();
+
+            match process_claim(app_database.clone(), rpc_pool.clone(), compute_unit_estimator.clone(), tx_manager.clone(), wallet.clone(), app_config.pool_id, miner.id, &miner.pubkey, amount, "claim", claim_settled_sender).await {
+                Ok(submission) => {
+                    let response = serde_json::json!({
+                        "claim_id": submission.claim_id,
+                        "signature": submission.signature.to_string(),
+                    });
+                    return Response::builder()
+                        .status(StatusCode::OK)
+                        .header("Content-Type", "application/json")
+                        .body(response.to_string())
+                        .unwrap();
+                },
+                Err(ClaimError::RewardsFrozen) => {
+                    return Response::builder()
+                        .status(StatusCode::FORBIDDEN)
+                        .body("miner rewards are frozen".to_string())
+                        .unwrap();
+                },
+                Err(ClaimError::InsufficientBalance) => {
+                    return Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body("claim amount exceeds miner rewards balance".to_string())
+                        .unwrap();
+                },
+                Err(e) => {
+                    println!("ERROR: {:?}", e);
+                    return Response::builder()
+                        .status(StatusCode::INTERNAL_SERVER_ERROR)
+                        .body("FAILED".to_string())
+                        .unwrap();
+                }
+            }
+        } else {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body("failed to get miner account from database".to_string())
+                .unwrap();
+        }
+    } else {
+        error!("Claim with invalid pubkey");
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid Pubkey".to_string())
+            .unwrap();
+    }
+}
+
+#[derive(Deserialize)]
+struct ClaimStatusParams {
+    id: i32,
+}
+
+async fn get_claim_status(
+    query_params: Query<ClaimStatusParams>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+) -> impl IntoResponse {
+    match app_database.get_claim_status(query_params.id).await {
+        Ok(claim) => {
+            let response = serde_json::json!({
+                "id": claim.id,
+                "status": claim.status,
+                "signature": claim.signature,
+                "amount": claim.amount,
+                "error": claim.error,
+            });
+            return Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "application/json")
+                .body(response.to_string())
+                .unwrap();
+        },
+        Err(AppDatabaseError::EntityDoesNotExist) => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body("Claim not found".to_string())
+                .unwrap();
+        },
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body("Failed to get claim status".to_string())
+                .unwrap();
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct PayoutThresholdParams {
+    threshold: u64,
+    timestamp: u64,
+}
+
+async fn post_miner_payout_threshold(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Basic>>,
+    query_params: Query<PayoutThresholdParams>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(used_signatures): Extension<Arc<Mutex<UsedSignatures>>>,
+) -> impl IntoResponse {
+    // threshold is 8 u8
+    let msg = signed_request_message(SIGNED_ACTION_PAYOUT_THRESHOLD, &query_params.threshold.to_le_bytes(), query_params.timestamp);
+    let user_pubkey = match verify_signed_request(&auth_header, query_params.timestamp, &msg, &used_signatures).await {
+        Ok(user_pubkey) => user_pubkey,
+        Err((status, reason)) => {
+            return Response::builder()
+                .status(status)
+                .body(reason.to_string())
+                .unwrap();
+        }
+    };
+
+    if query_params.threshold < app_config.auto_payout_threshold {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(format!("Threshold must be at least {}", app_config.auto_payout_threshold))
+            .unwrap();
+    }
+
+    let miner = match app_database.get_miner_by_pubkey_str(user_pubkey.to_string()).await {
+        Ok(miner) => miner,
+        Err(AppDatabaseError::EntityDoesNotExist) => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body("Miner not found".to_string())
+                .unwrap();
+        },
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body("Failed to get miner".to_string())
+                .unwrap();
+        }
+    };
+
+    if app_database.update_miner_payout_threshold(miner.id, query_params.threshold).await.is_ok() {
+        return Response::builder()
+            .status(StatusCode::OK)
+            .body("SUCCESS".to_string())
+            .unwrap();
+    } else {
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body("Failed to update payout threshold".to_string())
+            .unwrap();
+    }
+}
+
+/// Lists every miner paid in the last run, so it's admin only.
+async fn get_last_payout_report(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Bearer>>,
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(last_payout_report): Extension<Arc<Mutex<Option<PayoutReport>>>>,
+) -> impl IntoResponse {
+    if !is_admin(&auth_header, &app_config) {
+        return admin_unauthorized();
+    }
+
+    let report = last_payout_report.lock().await.clone();
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_string(&report).unwrap())
+        .unwrap()
+}
+
+/// Parses whitelist file contents, one pubkey per line.
+fn parse_whitelist(contents: &str) -> Vec<Pubkey> {
+    let mut pubkeys = Vec::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Ok(pubkey) = Pubkey::from_str(line) {
+            pubkeys.push(pubkey);
+        } else {
+            let err = format!("Failed to create pubkey from line {} with value: {}", i, line);
+            error!(err);
+        }
+    }
+    pubkeys
+}
+
+/// Admin routes authenticate with the pool PASSWORD as a bearer token.
+fn is_admin(auth_header: &axum_extra::headers::Authorization<Bearer>, app_config: &Config) -> bool {
+    let token = auth_header.token().as_bytes();
+    let password = app_config.password.as_bytes();
+    if token.len() != password.len() {
+        return false;
+    }
+    // constant time compare so response timing doesn't leak the password
+    token.iter().zip(password.iter()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+fn admin_unauthorized() -> Response<String> {
+    Response::builder()
+        .status(StatusCode::UNAUTHORIZED)
+        .body("Unauthorized".to_string())
+        .unwrap()
+}
+
+async fn get_admin_whitelist(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Bearer>>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> impl IntoResponse {
+    if !is_admin(&auth_header, &app_config) {
+        return admin_unauthorized();
+    }
+
+    match app_database.get_whitelist().await {
+        Ok(entries) => {
+            let pubkeys: Vec<String> = entries.into_iter().map(|e| e.pubkey).collect();
+            return Response::builder()
+                .status(StatusCode::OK)
+                .header("Content-Type", "application/json")
+                .body(serde_json::to_string(&pubkeys).unwrap())
+                .unwrap();
+        },
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body("Failed to get whitelist".to_string())
+                .unwrap();
+        }
+    }
+}
+
+async fn post_admin_whitelist(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Bearer>>,
+    query_params: Query<PubkeyParam>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> impl IntoResponse {
+    if !is_admin(&auth_header, &app_config) {
+        return admin_unauthorized();
+    }
+
+    if let Ok(pubkey) = Pubkey::from_str(&query_params.pubkey) {
+        if app_database.add_whitelist_entry(pubkey.to_string()).await.is_ok() {
+            info!("Added {} to whitelist", pubkey);
+            return Response::builder()
+                .status(StatusCode::OK)
+                .body("SUCCESS".to_string())
+                .unwrap();
+        } else {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body("Failed to add whitelist entry".to_string())
+                .unwrap();
+        }
+    } else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid public key".to_string())
+            .unwrap();
+    }
+}
+
+async fn delete_admin_whitelist(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Bearer>>,
+    query_params: Query<PubkeyParam>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> impl IntoResponse {
+    if !is_admin(&auth_header, &app_config) {
+        return admin_unauthorized();
+    }
+
+    if let Ok(pubkey) = Pubkey::from_str(&query_params.pubkey) {
+        match app_database.remove_whitelist_entry(pubkey.to_string()).await {
+            Ok(_) => {
+                info!("Removed {} from whitelist", pubkey);
+                return Response::builder()
+                    .status(StatusCode::OK)
+                    .body("SUCCESS".to_string())
+                    .unwrap();
+            },
+            Err(AppDatabaseError::EntityDoesNotExist) => {
+                return Response::builder()
+                    .status(StatusCode::NOT_FOUND)
+                    .body("Pubkey is not whitelisted".to_string())
+                    .unwrap();
+            },
+            Err(_) => {
+                return Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body("Failed to remove whitelist entry".to_string())
+                    .unwrap();
+            }
+        }
+    } else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid public key".to_string())
+            .unwrap();
+    }
+}
+
+async fn post_admin_whitelist_import(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Bearer>>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+    body: String,
+) -> impl IntoResponse {
+    if !is_admin(&auth_header, &app_config) {
+        return admin_unauthorized();
+    }
+
+    let pubkeys = parse_whitelist(&body);
+    for pubkey in pubkeys.iter() {
+        if app_database.add_whitelist_entry(pubkey.to_string()).await.is_err() {
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body("Failed to import whitelist".to_string())
+                .unwrap();
+        }
+    }
+
+    info!("Imported {} whitelist entries", pubkeys.len());
+    return Response::builder()
+        .status(StatusCode::OK)
+        .body(format!("{}", pubkeys.len()))
+        .unwrap();
+}
+
+#[derive(Deserialize)]
+struct AdminMinerParams {
+    pubkey: String,
+    reason: Option<String>,
+    freeze_rewards: Option<bool>,
+}
+
+async fn post_admin_miner_enable(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Bearer>>,
+    query_params: Query<AdminMinerParams>,
+    State(app_state): State<Arc<RwLock<AppState>>>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> impl IntoResponse {
+    if !is_admin(&auth_header, &app_config) {
+        return admin_unauthorized();
+    }
+    update_miner_status(&query_params, &app_state, &app_database, true, false).await
+}
+
+async fn post_admin_miner_disable(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Bearer>>,
+    query_params: Query<AdminMinerParams>,
+    State(app_state): State<Arc<RwLock<AppState>>>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> impl IntoResponse {
+    if !is_admin(&auth_header, &app_config) {
+        return admin_unauthorized();
+    }
+    update_miner_status(&query_params, &app_state, &app_database, false, false).await
+}
+
+async fn post_admin_miner_ban(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Bearer>>,
+    query_params: Query<AdminMinerParams>,
+    State(app_state): State<Arc<RwLock<AppState>>>,
+    Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(app_config): Extension<Arc<Config>>,
+) -> impl IntoResponse {
+    if !is_admin(&auth_header, &app_config) {
+        return admin_unauthorized();
+    }
+    update_miner_status(&query_params, &app_state, &app_database, false, true).await
+}
+
+async fn get_admin_bans(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Bearer>>,
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(rate_limiter): Extension<Arc<Mutex<RateLimiter>>>,
+) -> impl IntoResponse {
+    if !is_admin(&auth_header, &app_config) {
+        return admin_unauthorized();
+    }
+
+    let bans = rate_limiter.lock().await.bans();
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(serde_json::to_string(&bans).unwrap())
+        .unwrap()
+}
+
+#[derive(Deserialize)]
+struct AdminBanParams {
+    ip: String,
+}
+
+async fn delete_admin_ban(
+    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Bearer>>,
+    query_params: Query<AdminBanParams>,
+    Extension(app_config): Extension<Arc<Config>>,
+    Extension(rate_limiter): Extension<Arc<Mutex<RateLimiter>>>,
+) -> impl IntoResponse {
+    if !is_admin(&auth_header, &app_config) {
+        return admin_unauthorized();
+    }
+
+    let ip = if let Ok(ip) = IpAddr::from_str(&query_params.ip) {
+        ip
+    } else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid ip address".to_string())
+            .unwrap();
+    };
+
+    if !rate_limiter.lock().await.unban(&ip) {
+        return Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body("Ip is not banned".to_string())
+            .unwrap();
+    }
+
+    info!("Unbanned {}", ip);
+    Response::builder()
+        .status(StatusCode::OK)
+        .body("SUCCESS".to_string())
+        .unwrap()
+}
+
+/// Rejects http and websocket upgrade requests from banned or overly chatty ips.
+async fn rate_limit_middleware(
+    State(rate_limiter): State<Arc<Mutex<RateLimiter>>>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> axum::response::Response {
+    let result = {
+        let mut rate_limiter = rate_limiter.lock().await;
+        let ip = rate_limiter.client_ip(&addr, request.headers());
+        rate_limiter.check_ip(ip)
+    };
+    match result {
+        Ok(()) => next.run(request).await,
+        Err(RateLimitError::Banned) => {
+            (StatusCode::FORBIDDEN, "Your ip is temporarily banned").into_response()
+        },
+        Err(RateLimitError::Limited) => {
+            (StatusCode::TOO_MANY_REQUESTS, "Too many requests").into_response()
+        },
+    }
+}
+
+async fn update_miner_status(
+    params: &AdminMinerParams,
+    app_state: &Arc<RwLock<AppState>>,
+    app_database: &AppDatabase,
+    enabled: bool,
+    banned: bool,
+) -> Response<String> {
+    let user_pubkey = if let Ok(user_pubkey) = Pubkey::from_str(&params.pubkey) {
+        user_pubkey
+    } else {
+        return Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body("Invalid public key".to_string())
+            .unwrap();
+    };
 
-async fn get_miner_balance(
-    query_params: Query<PubkeyParam>,
-    Extension(rpc_client): Extension<Arc<RpcClient>>,
-) -> impl IntoResponse {
-    if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
-        let miner_token_account = get_associated_token_address(&user_pubkey, &get_ore_mint());
-        if let Ok(response) = rpc_client.get_token_account_balance(&miner_token_account).await {
+    let miner = match app_database.get_miner_by_pubkey_str(user_pubkey.to_string()).await {
+        Ok(miner) => miner,
+        Err(AppDatabaseError::EntityDoesNotExist) => {
             return Response::builder()
-                .status(StatusCode::OK)
-                .body(response.ui_amount_string)
+                .status(StatusCode::NOT_FOUND)
+                .body("Miner not found".to_string())
                 .unwrap();
-        } else {
+        },
+        Err(_) => {
             return Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .body("Failed to get token account balance".to_string())
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body("Failed to get miner".to_string())
                 .unwrap();
         }
-    } else {
+    };
+
+    // rewards stay as they are unless the call says otherwise
+    let rewards_frozen = params.freeze_rewards.unwrap_or(miner.rewards_frozen);
+    if app_database.update_miner_status(miner.id, enabled, banned, rewards_frozen, params.reason.clone()).await.is_err() {
         return Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .body("Invalid public key".to_string())
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body("Failed to update miner status".to_string())
             .unwrap();
     }
-}
-
-#[derive(Deserialize)]
-struct ClaimParams {
-    pubkey: String,
-    amount: u64,
-}
-
-async fn post_claim(
-    query_params: Query<ClaimParams>,
-    Extension(app_database): Extension<Arc<AppDatabase>>,
-    Extension(rpc_client): Extension<Arc<RpcClient>>,
-    Extension(wallet): Extension<Arc<Keypair>>,
-) -> impl IntoResponse {
-    if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
-        let amount = query_params.amount;
-        if let Ok(miner_rewards) = app_database.get_miner_rewards(user_pubkey.to_string()).await {
-            if amount > miner_rewards.balance {
-                return Response::builder()
-                    .status(StatusCode::BAD_REQUEST)
-                    .body("claim amount exceeds miner rewards balance".to_string())
-                    .unwrap();
-            }
-
-            let ore_mint = get_ore_mint();
-            let miner_token_account = get_associated_token_address(&user_pubkey, &ore_mint);
-
-            let mut ixs = Vec::new();
-            // let prio_fee_ix = ComputeBudgetInstruction::set_compute_unit_price(10000);
-            // ixs.push(prio_fee_ix);
-            if let Ok(response) = rpc_client.get_token_account_balance(&miner_token_account).await {
-                    if let Some(_amount) = response.ui_amount {
-                        info!("miner has valid token account.");
-                    } else {
-                        info!("will create token account for miner");
-                        ixs.push(spl_associated_token_account::instruction::create_associated_token_account(
-                            &wallet.pubkey(),
-                            &user_pubkey,
-                            &ore_api::consts::MINT_ADDRESS,
-                            &spl_token::id(),
-                        ))
-                    }
 
+    info!(
+        "Miner {} updated. enabled: {}, banned: {}, rewards frozen: {}, reason: {:?}",
+        user_pubkey, enabled, banned, rewards_frozen, params.reason
+    );
+
+    if !enabled {
+        // kick any connected sockets for this miner
+        let mut app_state = app_state.write().await;
+        let addrs: Vec<SocketAddr> = app_state.miner_sockets.get(&miner.id)
+            .map(|workers| workers.values().copied().collect())
+            .unwrap_or_default();
+        for addr in addrs {
+            let resume_id = if let Some(connection) = app_state.sockets.get(&addr) {
+                let reason = params.reason.clone().unwrap_or("Miner disabled".to_string());
+                // the writer task flushes the close frame and exits once the connection is dropped
+                connection.send(Message::Close(Some(CloseFrame {
+                    code: axum::extract::ws::close_code::POLICY,
+                    reason: reason.into(),
+                })));
+                info!("Kicked {} worker {} ({})", user_pubkey, connection.worker, addr);
+                connection.resume_id.clone()
             } else {
-                info!("Adding create ata ix for miner claim");
-                ixs.push(spl_associated_token_account::instruction::create_associated_token_account(
-                    &wallet.pubkey(),
-                    &user_pubkey,
-                    &ore_api::consts::MINT_ADDRESS,
-                    &spl_token::id(),
-                ))
+                None
+            };
+            // clears its work for the current round too, and a kicked worker has nothing to resume
+            remove_connection(&mut app_state, &addr);
+            if let Some(resume_id) = resume_id {
+                app_state.parked_sessions.remove(&resume_id);
             }
+        }
+    }
 
-            let ix = ore_api::instruction::claim(wallet.pubkey(), miner_token_account, amount);
-            ixs.push(ix);
-
-            if let Ok((hash, _slot)) = rpc_client
-                .get_latest_blockhash_with_commitment(rpc_client.commitment()).await {
-                let mut tx = Transaction::new_with_payer(&ixs, Some(&wallet.pubkey()));
-
-                tx.sign(&[&wallet], hash);
-
-                let result = rpc_client
-                    .send_and_confirm_transaction_with_spinner_and_commitment(
-                        &tx,
-                        rpc_client.commitment(),
-                    ).await;
-                match result {
-                    Ok(sig) => {
-                        info!("Miner successfully claimed.\nSig: {}", sig.to_string());
-
-                        // TODO: use transacions, or at least put them into one query
-                        let miner = app_database.get_miner_by_pubkey_str(user_pubkey.to_string()).await.unwrap();
-                        let db_pool = app_database.get_pool_by_authority_pubkey(wallet.pubkey().to_string()).await.unwrap();
-                        let _ = app_database.decrease_miner_reward(miner.id, amount).await.unwrap();
-                        let _ = app_database.update_pool_claimed(wallet.pubkey().to_string(), amount).await.unwrap();
-
-                        let itxn = InsertTxn {
-                            txn_type: "claim".to_string(),
-                            signature: sig.to_string(),
-                            priority_fee: 0,
-                        };
-                        let _ = app_database.add_new_txn(itxn).await.unwrap();
-
-                        let ntxn = app_database.get_txn_by_sig(sig.to_string()).await.unwrap();
-
+    Response::builder()
+        .status(StatusCode::OK)
+        .body("SUCCESS".to_string())
+        .unwrap()
+}
 
-                        let iclaim = InsertClaim {
-                            miner_id: miner.id,
-                            pool_id: db_pool.id,
-                            txn_id: ntxn.id,
-                            amount,
-                        };
-                        let _ = app_database.add_new_claim(iclaim).await.unwrap();
-
-                        return Response::builder()
-                            .status(StatusCode::OK)
-                            .body("SUCCESS".to_string())
-                            .unwrap();
-                    },
-                    Err(e) => {
-                        println!("ERROR: {:?}", e);
-                        return Response::builder()
-                            .status(StatusCode::INTERNAL_SERVER_ERROR)
-                            .body("FAILED".to_string())
-                            .unwrap();
-                    }
-                }
-            } else {
-                return Response::builder()
-                    .status(StatusCode::INTERNAL_SERVER_ERROR)
-                    .body("FAILED".to_string())
-                    .unwrap();
-            }
+#[derive(Deserialize)]
+struct WsQueryParams {
+    timestamp: Option<u64>,
+    nonce: Option<String>,
+    worker: Option<String>,
+    protocol: Option<String>,
+    resume: Option<String>,
+}
 
-        } else {
-            return Response::builder()
-                .status(StatusCode::INTERNAL_SERVER_ERROR)
-                .body("failed to get miner account from database".to_string())
-                .unwrap();
-        }
+async fn get_ws_nonce(
+    query_params: Query<PubkeyParam>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Extension(ws_auth): Extension<Arc<Mutex<WsAuth>>>,
+    Extension(rate_limiter): Extension<Arc<Mutex<RateLimiter>>>,
+) -> impl IntoResponse {
+    let user_pubkey = if let Ok(user_pubkey) = Pubkey::from_str(&query_params.pubkey) {
+        user_pubkey
     } else {
-        error!("Claim with invalid pubkey");
         return Response::builder()
             .status(StatusCode::BAD_REQUEST)
-            .body("Invalid Pubkey".to_string())
+            .body("Invalid public key".to_string())
             .unwrap();
-    }
+    };
+
+    let ip = rate_limiter.lock().await.client_ip(&addr, &headers);
+    let nonce = ws_auth.lock().await.issue_nonce(user_pubkey, ip);
+    let res = serde_json::json!({
+        "nonce": encode_ws_nonce(&nonce),
+        "expires_in": WS_AUTH_NONCE_MAX_AGE,
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body(res.to_string())
+        .unwrap()
 }
 
-#[derive(Deserialize)]
-struct WsQueryParams {
-    timestamp: u64
+fn is_valid_worker_name(worker: &str) -> bool {
+    !worker.is_empty()
+        && worker.len() <= MAX_WORKER_NAME_LEN
+        && worker.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
 }
 
 async fn ws_handler(
     ws: WebSocketUpgrade,
-    TypedHeader(auth_header): TypedHeader<axum_extra::headers::Authorization<Basic>>,
+    basic_auth: Option<TypedHeader<axum_extra::headers::Authorization<Basic>>>,
+    session_auth: Option<TypedHeader<axum_extra::headers::Authorization<Bearer>>>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     State(app_state): State<Arc<RwLock<AppState>>>,
-    //Extension(app_config): Extension<Arc<Config>>,
+    Extension(app_config): Extension<Arc<Config>>,
     Extension(client_channel): Extension<UnboundedSender<ClientMessage>>,
     Extension(app_database): Extension<Arc<AppDatabase>>,
+    Extension(wallet): Extension<Arc<Keypair>>,
+    Extension(ws_auth): Extension<Arc<Mutex<WsAuth>>>,
+    Extension(rate_limiter): Extension<Arc<Mutex<RateLimiter>>>,
     query_params: Query<WsQueryParams>
 ) -> impl IntoResponse {
+    let worker = query_params.worker.clone().unwrap_or(DEFAULT_WORKER_NAME.to_string());
+    if !is_valid_worker_name(&worker) {
+        return Err((StatusCode::BAD_REQUEST, "Invalid worker name"));
+    }
 
-    let msg_timestamp = query_params.timestamp;
+    let protocol = if let Some(protocol) = WsProtocol::from_param(query_params.protocol.as_deref()) {
+        protocol
+    } else {
+        return Err((StatusCode::BAD_REQUEST, "Unknown protocol, expected legacy, json or binary"));
+    };
 
-    let pubkey = auth_header.username();
-    let signed_msg = auth_header.password();
+    let ip = rate_limiter.lock().await.client_ip(&addr, &headers);
 
-    let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
+    // verify client, either with a session token from an earlier connection
+    // or a signature over a nonce issued by /ws/nonce
+    let (user_pubkey, auth_method) = match authenticate_ws_client(basic_auth, session_auth, &query_params, &app_config, &wallet, &ws_auth).await {
+        Ok(auth) => auth,
+        Err(e) => {
+            if rate_limiter.lock().await.record_failure(ip, "repeated websocket auth failures") {
+                info!("Banned {} after repeated websocket auth failures", ip);
+            }
+            return Err(e);
+        }
+    };
 
-    // Signed authentication message is only valid for 5 seconds
-    if (now - query_params.timestamp) >= 5 {
-        return Err((StatusCode::UNAUTHORIZED, "Timestamp too old."));
+    if rate_limiter.lock().await.check_pubkey(user_pubkey).is_err() {
+        return Err((StatusCode::TOO_MANY_REQUESTS, "Too many connection attempts for this pubkey"));
     }
 
-
-    // verify client
-    if let Ok(user_pubkey) = Pubkey::from_str(pubkey) {
-        {
-            let mut already_connected = false;
-            for (_, (socket_pubkey, _)) in app_state.read().await.sockets.iter() {
-                if user_pubkey == *socket_pubkey {
-                    already_connected = true;
-                    break;
-                }
+    let miner = match app_database.get_miner_by_pubkey_str(user_pubkey.to_string()).await {
+        Ok(db_miner) => {
+            if db_miner.banned {
+                return Err((StatusCode::FORBIDDEN, "pubkey is banned"));
             }
-            if already_connected {
-                return Err((StatusCode::TOO_MANY_REQUESTS, "A client is already connected with that wallet"));
+            if !db_miner.enabled {
+                return Err((StatusCode::UNAUTHORIZED, "pubkey is not authorized to mine"));
+            }
+            db_miner
+        }
+        Err(AppDatabaseError::EntityDoesNotExist) => {
+            // Whitelisted pubkeys can start mining without signing up first
+            match app_database.is_whitelisted(user_pubkey.to_string()).await {
+                Ok(true) => {},
+                Ok(false) => {
+                    return Err((StatusCode::UNAUTHORIZED, "pubkey is not authorized to mine. please sign up."));
+                },
+                Err(_) => {
+                    error!("Failed to get database pool connection.");
+                    return Err((StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"));
+                }
             }
-        };
-
-        let db_miner = app_database.get_miner_by_pubkey_str(pubkey.to_string()).await;
 
-        let miner;
-        match db_miner {
-            Ok(db_miner) => {
-                miner = db_miner;
+            info!("Enrolling whitelisted miner {}", user_pubkey);
+            if app_database.add_new_miner(user_pubkey.to_string(), true).await.is_err() {
+                return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to add miner to database"));
             }
-            Err(AppDatabaseError::EntityDoesNotExist) => {
-                return Err((StatusCode::UNAUTHORIZED, "pubkey is not authorized to mine. please sign up."));
-            },
-            Err(_) => {
-                error!("Failed to get database pool connection.");
+            let miner = if let Ok(miner) = app_database.get_miner_by_pubkey_str(user_pubkey.to_string()).await {
+                miner
+            } else {
                 return Err((StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"));
+            };
+            let new_reward = InsertReward {
+                miner_id: miner.id,
+                pool_id: app_config.pool_id,
+            };
+            if app_database.add_new_reward(new_reward).await.is_err() {
+                return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to add miner rewards tracker to database"));
             }
+            miner
+        },
+        Err(_) => {
+            error!("Failed to get database pool connection.");
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"));
         }
+    };
 
-        if !miner.enabled {
-            return Err((StatusCode::UNAUTHORIZED, "pubkey is not authorized to mine"));
+    {
+        let app_state = app_state.read().await;
+        let max_connections_per_ip = rate_limiter.lock().await.max_connections_per_ip();
+        if app_state.sockets.values().filter(|connection| connection.ip == ip).count() >= max_connections_per_ip {
+            return Err((StatusCode::TOO_MANY_REQUESTS, "Too many connections from this ip"));
         }
-
-        if let Ok(signature) = Signature::from_str(signed_msg) {
-            let ts_msg = msg_timestamp.to_le_bytes();
-            
-            if signature.verify(&user_pubkey.to_bytes(), &ts_msg) {
-                println!("Client: {addr} connected with pubkey {pubkey}.");
-                return Ok(ws.on_upgrade(move |socket| handle_socket(socket, addr, user_pubkey, app_state, client_channel, miner.id)));
-            } else {
-                return Err((StatusCode::UNAUTHORIZED, "Sig verification failed"));
+        if let Some(workers) = app_state.miner_sockets.get(&miner.id) {
+            // a resuming client replaces its old connection if that hasn't been dropped yet
+            let replaces_own_connection = query_params.resume.is_some() && workers.get(&worker)
+                .and_then(|addr| app_state.sockets.get(addr))
+                .map_or(false, |connection| connection.resume_id == query_params.resume);
+            if replaces_own_connection {
+                info!("{} worker {} is resuming over its old connection", user_pubkey, worker);
+            } else if workers.contains_key(&worker) {
+                return Err((StatusCode::TOO_MANY_REQUESTS, "A worker with that name is already connected"));
+            } else if workers.len() >= app_config.max_workers_per_wallet {
+                return Err((StatusCode::TOO_MANY_REQUESTS, "Too many workers connected with that wallet"));
             }
+        }
+    }
+
+    println!("Client: {addr} connected with pubkey {user_pubkey} as worker {worker}.");
+    let max_workers = app_config.max_workers_per_wallet;
+    let client = WsClient {
+        pubkey: user_pubkey,
+        miner_id: miner.id,
+        worker,
+        protocol,
+        resume_id: if app_config.ws_resume_grace > 0 { Some(new_resume_id()) } else { None },
+        resume_from: query_params.resume.clone(),
+        ip,
+    };
+    let resume_id = client.resume_id.clone();
+    let mut res = ws.on_upgrade(move |socket| handle_socket(socket, addr, client, app_state, client_channel, max_workers));
+
+    let session_expires_at = match auth_method {
+        _ if app_config.ws_session_ttl == 0 => None,
+        // a reconnect with a session token gets a replacement expiring with the original
+        WsAuthMethod::Session(expires_at) => Some(expires_at),
+        WsAuthMethod::Nonce => Some(Instant::now() + Duration::from_secs(app_config.ws_session_ttl)),
+        WsAuthMethod::LegacyTimestamp => None,
+    };
+    if let Some(expires_at) = session_expires_at {
+        let token = ws_auth.lock().await.issue_session(user_pubkey, expires_at);
+        if let Ok(token) = HeaderValue::from_str(&token) {
+            res.headers_mut().insert("x-session-token", token);
+            res.headers_mut().insert("x-session-expires-in", HeaderValue::from(expires_at.saturating_duration_since(Instant::now()).as_secs()));
+        }
+    }
+
+    if let Some(resume_id) = resume_id.and_then(|id| HeaderValue::from_str(&id).ok()) {
+        res.headers_mut().insert("x-resume-id", resume_id);
+    }
+
+    return Ok(res);
+}
+
+/// Resolves the pubkey a websocket client connects as, either from a session token
+/// issued on an earlier connection or a signature over a nonce issued by /ws/nonce.
+/// Session tokens are used up. Clients from before nonces are accepted with a signature
+/// over the timestamp while `ws_legacy_auth` is set.
+async fn authenticate_ws_client(
+    basic_auth: Option<TypedHeader<axum_extra::headers::Authorization<Basic>>>,
+    session_auth: Option<TypedHeader<axum_extra::headers::Authorization<Bearer>>>,
+    query_params: &WsQueryParams,
+    app_config: &Config,
+    wallet: &Keypair,
+    ws_auth: &Mutex<WsAuth>,
+) -> Result<(Pubkey, WsAuthMethod), (StatusCode, &'static str)> {
+    if let Some(TypedHeader(session_auth)) = session_auth {
+        if let Some(session) = ws_auth.lock().await.take_session(session_auth.token()) {
+            Ok((session.pubkey, WsAuthMethod::Session(session.expires_at)))
+        } else {
+            Err((StatusCode::UNAUTHORIZED, "Invalid or expired session token"))
+        }
+    } else if let Some(TypedHeader(basic_auth)) = basic_auth {
+        let user_pubkey = if let Ok(user_pubkey) = Pubkey::from_str(basic_auth.username()) {
+            user_pubkey
+        } else {
+            return Err((StatusCode::UNAUTHORIZED, "Invalid pubkey"));
+        };
+
+        let timestamp = match query_params.timestamp {
+            Some(timestamp) => timestamp,
+            None => return Err((StatusCode::UNAUTHORIZED, "Missing timestamp")),
+        };
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
+        if now.abs_diff(timestamp) > app_config.ws_auth_max_skew {
+            return Err((StatusCode::UNAUTHORIZED, "Timestamp outside allowed skew."));
+        }
+
+        let signature = if let Ok(signature) = Signature::from_str(basic_auth.password()) {
+            signature
         } else {
             return Err((StatusCode::UNAUTHORIZED, "Invalid signature"));
+        };
+
+        let nonce = match (&query_params.nonce, app_config.ws_legacy_auth) {
+            (Some(nonce), _) => nonce,
+            // clients from before nonces sign just the timestamp
+            (None, true) => {
+                if !signature.verify(&user_pubkey.to_bytes(), &timestamp.to_le_bytes()) {
+                    return Err((StatusCode::UNAUTHORIZED, "Sig verification failed"));
+                }
+                return Ok((user_pubkey, WsAuthMethod::LegacyTimestamp));
+            },
+            (None, false) => return Err((StatusCode::UNAUTHORIZED, "Missing nonce")),
+        };
+
+        let nonce = match decode_ws_nonce(nonce) {
+            Ok(nonce) => nonce,
+            Err(e) => return Err((StatusCode::UNAUTHORIZED, e.message())),
+        };
+
+        // nonces are single use, even when the signature turns out to be bad
+        if let Err(e) = ws_auth.lock().await.take_nonce(&nonce, &user_pubkey) {
+            return Err((StatusCode::UNAUTHORIZED, e.message()));
+        }
+
+        let msg = ws_auth_message(&nonce, timestamp, &wallet.pubkey());
+        if !signature.verify(&user_pubkey.to_bytes(), &msg) {
+            return Err((StatusCode::UNAUTHORIZED, "Sig verification failed"));
         }
+
+        Ok((user_pubkey, WsAuthMethod::Nonce))
     } else {
-        return Err((StatusCode::UNAUTHORIZED, "Invalid pubkey"));
+        Err((StatusCode::UNAUTHORIZED, "Missing authorization"))
     }
-
 }
 
-async fn handle_socket(mut socket: WebSocket, who: SocketAddr, who_pubkey: Pubkey, rw_app_state: Arc<RwLock<AppState>>, client_channel: UnboundedSender<ClientMessage>, miner_id: i32) {
+async fn handle_socket(mut socket: WebSocket, who: SocketAddr, client: WsClient, rw_app_state: Arc<RwLock<AppState>>, client_channel: UnboundedSender<ClientMessage>, max_workers: usize) {
+    let WsClient { pubkey: who_pubkey, miner_id, worker, protocol, resume_id, resume_from, ip } = client;
     if socket.send(axum::extract::ws::Message::Ping(vec![1, 2, 3])).await.is_ok() {
         println!("Pinged {who}...");
     } else {
@@ -1162,35 +3309,129 @@ async fn handle_socket(mut socket: WebSocket, who: SocketAddr, who_pubkey: Pubke
         return;
     }
 
-    let (sender, mut receiver) = socket.split();
+    let (mut sink, mut receiver) = socket.split();
+    let (sender, mut queue) = tokio::sync::mpsc::channel::<Message>(WS_SEND_QUEUE_SIZE);
+    let close = Arc::new(Notify::new());
+    let health = Arc::new(ConnectionHealth::new());
     let mut app_state = rw_app_state.write().await;
     if app_state.sockets.contains_key(&who) {
         println!("Socket addr: {who} already has an active connection");
         return;
-    } else {
-        let sender = Arc::new(Mutex::new(sender));
-        app_state.sockets.insert(who, (who_pubkey, sender.clone()));
-        app_state.miner_sockets.insert(miner_id, (who_pubkey, sender));
+    }
+    if let Some(resume_from) = &resume_from {
+        // the old connection may still look alive if the client noticed the drop before we did
+        let old_addr = app_state.miner_sockets.get(&miner_id).and_then(|workers| workers.get(&worker)).cloned();
+        if let Some(old_addr) = old_addr {
+            if let Some(old_connection) = app_state.sockets.get(&old_addr).filter(|c| c.resume_id.as_ref() == Some(resume_from)) {
+                old_connection.close.notify_one();
+                remove_connection(&mut app_state, &old_addr);
+            }
+        }
+    }
+    // checked again under the write lock, workers may have connected since the upgrade was accepted
+    let workers = app_state.miner_sockets.entry(miner_id).or_default();
+    if workers.contains_key(&worker) || workers.len() >= max_workers {
+        println!("Socket addr: {who} rejected, worker limit reached for {who_pubkey}");
+        return;
+    }
+    workers.insert(worker.clone(), who);
+    app_state.sockets.insert(who, AppClientConnection {
+        pubkey: who_pubkey,
+        miner_id,
+        worker: worker.clone(),
+        ip,
+        protocol,
+        health: health.clone(),
+        sender,
+        close: close.clone(),
+        resume_id,
+        stats: std::sync::Mutex::new(SessionStats::new()),
+        stats_requested_at: std::sync::Mutex::new(None),
+    });
+    let resumed = resume_from
+        .and_then(|id| app_state.parked_sessions.remove(&id))
+        .filter(|parked| parked.miner_id == miner_id && parked.worker == worker && parked.expires_at > Instant::now());
+    if let Some(parked) = resumed {
+        info!("{} worker {} resumed with nonces {:?}", who_pubkey, worker, parked.assignment.range);
+        app_state.nonce_ranges.insert(who, parked.assignment);
+        if let Some(connection) = app_state.sockets.get(&who) {
+            *connection.stats.lock().unwrap() = parked.stats;
+            connection.send_event(&ServerEvent::Notice(NoticeEvent {
+                message: String::from("Session resumed, submit your best solution for the current challenge."),
+            }));
+        }
     }
     drop(app_state);
 
-    let _ = tokio::spawn(async move {
+    // The writer task is the only one touching the sink, so a slow client only ever blocks itself
+    let mut send_task = tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                msg = queue.recv() => {
+                    if let Some(msg) = msg {
+                        if sink.send(msg).await.is_err() {
+                            break;
+                        }
+                    } else {
+                        break;
+                    }
+                },
+                _ = close.notified() => {
+                    break;
+                }
+            }
+        }
+        let _ = sink.close().await;
+    });
+
+    let mut recv_task = tokio::spawn(async move {
         while let Some(Ok(msg)) = receiver.next().await {
-            if process_message(msg, who, client_channel.clone()).is_break() {
+            if process_message(msg, who, client_channel.clone(), &health).is_break() {
                 break;
             }
         }
-    }).await;
+    });
+
+    tokio::select! {
+        _ = &mut send_task => recv_task.abort(),
+        _ = &mut recv_task => send_task.abort(),
+    }
 
     let mut app_state = rw_app_state.write().await;
-    app_state.sockets.remove(&who);
-    app_state.miner_sockets.remove(&miner_id);
+    remove_connection(&mut app_state, &who);
     drop(app_state);
 
-    info!("Client: {} disconnected!", who_pubkey.to_string());
+    info!("Client: {} worker {} disconnected!", who_pubkey.to_string(), worker);
+}
+
+/// Drops every piece of state kept for the connection at `who`.
+fn remove_connection(app_state: &mut AppState, who: &SocketAddr) {
+    app_state.ready_clients.remove(who);
+    let assignment = app_state.nonce_ranges.remove(who);
+    if let Some(connection) = app_state.sockets.remove(who) {
+        if let (Some(resume_id), Some(assignment)) = (connection.resume_id.clone(), assignment) {
+            let expires_at = Instant::now() + app_state.resume_grace;
+            app_state.parked_sessions.insert(resume_id, ParkedSession {
+                miner_id: connection.miner_id,
+                worker: connection.worker.clone(),
+                assignment,
+                stats: connection.stats.into_inner().unwrap(),
+                expires_at,
+            });
+        }
+        if let Some(workers) = app_state.miner_sockets.get_mut(&connection.miner_id) {
+            // only drop the worker entry if it still points at this connection
+            if workers.get(&connection.worker) == Some(who) {
+                workers.remove(&connection.worker);
+            }
+            if workers.is_empty() {
+                app_state.miner_sockets.remove(&connection.miner_id);
+            }
+        }
+    }
 }
 
-fn process_message(msg: Message, who: SocketAddr, client_channel: UnboundedSender<ClientMessage>) -> ControlFlow<(), ()> {
+fn process_message(msg: Message, who: SocketAddr, client_channel: UnboundedSender<ClientMessage>, health: &ConnectionHealth) -> ControlFlow<(), ()> {
     match msg {
         Message::Text(t) => {
             println!(">>> {who} sent str: {t:?}");
@@ -1218,8 +3459,7 @@ fn process_message(msg: Message, who: SocketAddr, client_channel: UnboundedSende
                     let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
 
 
-                    let time_since = now - ts;
-                    if time_since > 5 {
+                    if now.abs_diff(ts) > 5 {
                         error!("Client tried to ready up with expired signed message");
                         return ControlFlow::Break(());
                     }
@@ -1231,6 +3471,10 @@ fn process_message(msg: Message, who: SocketAddr, client_channel: UnboundedSende
                     let msg = ClientMessage::Mining(who);
                     let _ = client_channel.send(msg);
                 },
+                3 => {
+                    let msg = ClientMessage::StatsRequest(who);
+                    let _ = client_channel.send(msg);
+                },
                 2 => {
                     // parse solution from message data
                     let mut solution_bytes = [0u8; 16];
@@ -1300,8 +3544,8 @@ fn process_message(msg: Message, who: SocketAddr, client_channel: UnboundedSende
             }
             return ControlFlow::Break(())
         },
-        Message::Pong(_v) => {
-            //println!(">>> {who} sent pong with {v:?}");
+        Message::Pong(v) => {
+            health.record_pong(&v);
         },
         Message::Ping(_v) => {
             //println!(">>> {who} sent ping with {v:?}");
@@ -1312,18 +3556,21 @@ fn process_message(msg: Message, who: SocketAddr, client_channel: UnboundedSende
 }
 
 async fn proof_tracking_system(
-    ws_url: String,
+    rpc_pool: Arc<RpcPool>,
     wallet: Arc<Keypair>,
     proof: Arc<Mutex<Proof>>,
 ) { 
+    // index of the websocket url in use, moves on to the next url whenever a connection fails or drops
+    let mut ws_attempt = 0;
     loop {
         println!("Establishing rpc websocket connection...");
-        let mut ps_client = PubsubClient::new(&ws_url).await;
+        let mut ps_client = PubsubClient::new(rpc_pool.ws_url(ws_attempt)).await;
         let mut attempts = 0;
         
         while ps_client.is_err() && attempts < 3 {
-            error!("Failed to connect to websocket, retrying...");
-            ps_client = PubsubClient::new(&ws_url).await;
+            error!("Failed to connect to websocket {}, retrying...", rpc_pool.ws_url(ws_attempt));
+            ws_attempt += 1;
+            ps_client = PubsubClient::new(rpc_pool.ws_url(ws_attempt)).await;
             tokio::time::sleep(Duration::from_millis(1000)).await;
             attempts += 1;
         }
@@ -1368,6 +3615,8 @@ async fn proof_tracking_system(
                         }
                     }
                 }
+            error!("Lost websocket {}, failing over", rpc_pool.ws_url(ws_attempt));
+            ws_attempt += 1;
         }
     }
 }
@@ -1376,24 +3625,24 @@ async fn client_message_handler_system(
     mut receiver_channel: UnboundedReceiver<ClientMessage>,
     shared_state: &Arc<RwLock<AppState>>,
     app_database: Arc<AppDatabase>,
-    ready_clients: Arc<Mutex<HashSet<SocketAddr>>>,
     proof: Arc<Mutex<Proof>>,
-    client_nonce_ranges: Arc<RwLock<HashMap<Pubkey, Range<u64>>>>
+    rate_limiter: Arc<Mutex<RateLimiter>>,
 ) {
     while let Some(client_message) = receiver_channel.recv().await {
         match client_message {
             ClientMessage::Ready(addr) => {
                 info!("Client {} is ready!", addr.to_string());
                 {
-                    let shared_state = shared_state.read().await;
-                    if let Some(sender) = shared_state.sockets.get(&addr) {
-                        {
-                            let mut ready_clients = ready_clients.lock().await;
-                            ready_clients.insert(addr);
-                        }
+                    let mut shared_state = shared_state.write().await;
+                    if shared_state.sockets.contains_key(&addr) {
+                        shared_state.ready_clients.insert(addr);
+                    }
+                    if let Some(connection) = shared_state.sockets.get(&addr) {
 
-                        if let Ok(_) = sender.1.lock().await.send(Message::Text(String::from("Client successfully added."))).await {
-                        } else {
+                        let notice = ServerEvent::Notice(NoticeEvent {
+                            message: String::from("Client successfully added."),
+                        });
+                        if !connection.send_event(&notice) {
                             println!("Failed notify client they were readied up!");
                         }
                     }
@@ -1402,12 +3651,37 @@ async fn client_message_handler_system(
             ClientMessage::Mining(addr) => {
                 println!("Client {} has started mining!", addr.to_string());
             },
-            ClientMessage::BestSolution(_addr, solution, pubkey) => {
+            ClientMessage::StatsRequest(addr) => {
+                let allowed = shared_state.read().await.sockets.get(&addr).map_or(false, |connection| connection.allow_stats_request());
+                if allowed {
+                    // answered off this loop so the db query doesn't hold up share processing
+                    let shared_state = shared_state.clone();
+                    let app_database = app_database.clone();
+                    tokio::spawn(async move {
+                        send_stats(&shared_state, &app_database, &addr).await;
+                    });
+                }
+            },
+            ClientMessage::BestSolution(addr, solution, pubkey) => {
                 let pubkey_str = pubkey.to_string();
                 let challenge = app_database.get_latest_challenge().await.unwrap();
 
-                let nonce_range: Range<u64> = {
-                    if let Some(nr) = client_nonce_ranges.read().await.get(&pubkey) {
+                let (miner_id, worker) = {
+                    let shared_state = shared_state.read().await;
+                    if let Some(connection) = shared_state.sockets.get(&addr) {
+                        if connection.pubkey != pubkey {
+                            error!("Client {} submitted a solution for another wallet", addr);
+                            continue;
+                        }
+                        (connection.miner_id, connection.worker.clone())
+                    } else {
+                        error!("Solution from unknown client {}", addr);
+                        continue;
+                    }
+                };
+
+                let assignment = {
+                    if let Some(nr) = shared_state.read().await.nonce_ranges.get(&addr) {
                         nr.clone()
                     } else {
                         error!("Client nonce range not set!");
@@ -1417,8 +3691,16 @@ async fn client_message_handler_system(
 
                 let nonce = u64::from_le_bytes(solution.n);
 
-                if !nonce_range.contains(&nonce) {
+                // work carried over from before a reconnect may be for a challenge that has since been mined
+                if assignment.challenge.as_slice() != challenge.challenge.as_slice() {
+                    reject_share(shared_state, &addr, nonce, "stale_challenge", "stale challenge".to_string()).await;
+                    continue;
+                }
+
+                if !assignment.range.contains(&nonce) {
                     error!("Client submitted nonce out of assigned range");
+                    // late shares from a previous range land here too, so this isn't a strike
+                    reject_share(shared_state, &addr, nonce, "out_of_range", "nonce out of assigned range".to_string()).await;
                     continue;
                 }
 
@@ -1429,52 +3711,139 @@ async fn client_message_handler_system(
                         // calculate rewards
                         info!("CHALLENGE: {:?}", challenge);
 
-                        let miner = app_database.get_miner_by_pubkey_str(pubkey_str).await.unwrap();
-
                         let new_submission = InsertSubmission {
-                            miner_id: miner.id,
+                            miner_id,
                             challenge_id: challenge.id,
                             digest: Some(solution.d.to_vec()),
                             nonce,
                             difficulty: diff as i8,
+                            worker,
                         };
 
                         info!("NEW SUBMISSION: {:?}", new_submission);
                         let _ = app_database.add_new_submission(new_submission).await.unwrap();
+                        if let Some(connection) = shared_state.read().await.sockets.get(&addr) {
+                            connection.stats.lock().unwrap().record_accepted(diff);
+                            connection.send_event(&ServerEvent::ShareAccepted(ShareAcceptedEvent {
+                                difficulty: diff,
+                                nonce,
+                            }));
+                        }
                     } else {
                         error!("Diff to low, skipping");
+                        reject_share(shared_state, &addr, nonce, "low_difficulty", format!("difficulty {} is below the minimum of {}", diff, MIN_DIFF)).await;
                     }
                 } else {
                     error!("{} returned an invalid solution!", pubkey);
-                    return;
+                    reject_share(shared_state, &addr, nonce, "invalid_solution", "invalid solution".to_string()).await;
+                    record_bad_submission(shared_state, &rate_limiter, &addr).await;
                 }
             }
         }
     }
 }
 
+/// Counts a bad submission against the client's ip, dropping every connection
+/// from that ip if it gets banned.
+async fn record_bad_submission(shared_state: &Arc<RwLock<AppState>>, rate_limiter: &Mutex<RateLimiter>, addr: &SocketAddr) {
+    let ip = if let Some(connection) = shared_state.read().await.sockets.get(addr) {
+        connection.ip
+    } else {
+        return;
+    };
+    if !rate_limiter.lock().await.record_failure(ip, "repeated invalid solutions") {
+        return;
+    }
+
+    info!("Banned {} after repeated invalid solutions", ip);
+    let mut shared_state = shared_state.write().await;
+    let addrs: Vec<SocketAddr> = shared_state.sockets.iter()
+        .filter(|(_, connection)| connection.ip == ip)
+        .map(|(addr, _)| *addr)
+        .collect();
+    for addr in addrs {
+        if let Some(connection) = shared_state.sockets.get(&addr) {
+            connection.close.notify_one();
+        }
+        remove_connection(&mut shared_state, &addr);
+    }
+}
+
+/// Tells the client why its share didn't count and bumps the matching session counter.
+async fn reject_share(shared_state: &Arc<RwLock<AppState>>, addr: &SocketAddr, nonce: u64, kind: &'static str, reason: String) {
+    if let Some(connection) = shared_state.read().await.sockets.get(addr) {
+        connection.stats.lock().unwrap().record_rejected(kind);
+        connection.send_event(&ServerEvent::ShareRejected(ShareRejectedEvent {
+            reason,
+            nonce,
+        }));
+    }
+}
+
+async fn send_stats(shared_state: &Arc<RwLock<AppState>>, app_database: &AppDatabase, addr: &SocketAddr) {
+    let pubkey = if let Some(connection) = shared_state.read().await.sockets.get(addr) {
+        connection.pubkey
+    } else {
+        return;
+    };
+
+    // the balance is read before taking the lock again so the db call doesn't hold it
+    let balance = match app_database.get_miner_rewards(pubkey.to_string()).await {
+        Ok(rewards) => rewards.balance,
+        Err(_) => {
+            error!("Failed to get rewards balance for {}", pubkey);
+            return;
+        }
+    };
+
+    if let Some(connection) = shared_state.read().await.sockets.get(addr) {
+        let event = connection.stats.lock().unwrap().to_event(balance, connection.health.rtt_ms());
+        connection.send_event(&event);
+    }
+}
+
+/// Pushes a stats event to every connected client that understands it each `interval`.
+async fn stats_push_system(shared_state: &Arc<RwLock<AppState>>, app_database: Arc<AppDatabase>, interval: Duration) {
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let addrs: Vec<SocketAddr> = shared_state.read().await.sockets.iter()
+            .filter(|(_, connection)| connection.protocol != WsProtocol::Legacy)
+            .map(|(addr, _)| *addr)
+            .collect();
+        for addr in addrs {
+            send_stats(shared_state, &app_database, &addr).await;
+        }
+    }
+}
+
 async fn ping_check_system(
     shared_state: &Arc<RwLock<AppState>>,
+    idle_timeout: Duration,
 ) {
     loop {
-        // send ping to all sockets
+        // send ping to all sockets, the payload is echoed back so the pong gives us the round trip time
         let mut failed_sockets = Vec::new();
+        let ping_payload = now_millis().to_le_bytes().to_vec();
         let app_state = shared_state.read().await;
-        // I don't like doing all this work while holding this lock...
-        for (who, socket) in app_state.sockets.iter() {
-            if socket.1.lock().await.send(Message::Ping(vec![1, 2, 3])).await.is_ok() {
-                //println!("Pinged: {who}...");
-            } else {
+        for (who, connection) in app_state.sockets.iter() {
+            if connection.health.idle_for() > idle_timeout {
+                info!("No pong from {} worker {} in {}s, disconnecting", connection.pubkey, connection.worker, idle_timeout.as_secs());
+                connection.close.notify_one();
+                failed_sockets.push(who.clone());
+            } else if !connection.send(Message::Ping(ping_payload.clone())) {
                 failed_sockets.push(who.clone());
             }
         }
         drop(app_state);
 
-        // remove any sockets where ping failed
+        // remove any sockets that are gone or went quiet
         let mut app_state = shared_state.write().await;
         for address in failed_sockets {
-             app_state.sockets.remove(&address);
+             remove_connection(&mut app_state, &address);
         }
+        let now = Instant::now();
+        app_state.parked_sessions.retain(|_, parked| parked.expires_at > now);
         drop(app_state);
 
         tokio::time::sleep(Duration::from_secs(5)).await;
@@ -47,6 +47,39 @@ pub struct InsertClaim {
     pub pool_id: i32,
     pub txn_id: i32,
     pub amount: u64,
+    pub status: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, QueryableByName)]
+pub struct ClaimStatus {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub id: i32,
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub miner_id: i32,
+    #[diesel(sql_type = diesel::sql_types::Unsigned<diesel::sql_types::BigInt>)]
+    pub amount: u64,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub status: String,
+    #[diesel(sql_type = diesel::sql_types::Nullable<diesel::sql_types::Text>)]
+    pub error: Option<String>,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub signature: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, QueryableByName)]
+pub struct ClaimWithTxn {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub id: i32,
+    #[diesel(sql_type = diesel::sql_types::Unsigned<diesel::sql_types::BigInt>)]
+    pub amount: u64,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub status: String,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub signature: String,
+    #[diesel(sql_type = diesel::sql_types::Unsigned<diesel::sql_types::Integer>)]
+    pub priority_fee: u32,
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub created_at: NaiveDateTime,
 }
 
 #[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
@@ -55,7 +88,38 @@ pub struct InsertClaim {
 pub struct Miner {
     pub id: i32,
     pub pubkey: String,
-    pub enabled: bool
+    pub enabled: bool,
+    pub banned: bool,
+    pub rewards_frozen: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
+#[diesel(table_name = crate::schema::miners)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct MinerPayoutAddress {
+    pub id: i32,
+    pub payout_pubkey: Option<String>,
+    pub pending_payout_pubkey: Option<String>,
+    pub pending_payout_available_at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
+#[diesel(table_name = crate::schema::miners)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct MinerReferral {
+    pub id: i32,
+    pub referral_code: Option<String>,
+    pub referrer_id: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize, QueryableByName)]
+pub struct MinerPayoutCandidate {
+    #[diesel(sql_type = diesel::sql_types::Integer)]
+    pub miner_id: i32,
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub pubkey: String,
+    #[diesel(sql_type = diesel::sql_types::Unsigned<diesel::sql_types::BigInt>)]
+    pub balance: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
@@ -67,6 +131,7 @@ pub struct Pool {
     pub authority_pubkey: String,
     pub total_rewards: u64,
     pub claimed_rewards: u64,
+    pub total_staked: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
@@ -78,6 +143,7 @@ pub struct Submission {
     pub challenge_id: i32,
     pub nonce: u64,
     pub difficulty: i8,
+    pub worker: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
@@ -89,6 +155,19 @@ pub struct InsertSubmission {
     pub digest: Option<Vec<u8>>,
     pub nonce: u64,
     pub difficulty: i8,
+    pub worker: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, QueryableByName)]
+pub struct WorkerStats {
+    #[diesel(sql_type = diesel::sql_types::Text)]
+    pub worker: String,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub submissions: i64,
+    #[diesel(sql_type = diesel::sql_types::TinyInt)]
+    pub best_difficulty: i8,
+    #[diesel(sql_type = diesel::sql_types::Timestamp)]
+    pub last_submission_at: NaiveDateTime,
 }
 
 
@@ -133,6 +212,8 @@ pub struct InsertTxn {
     pub txn_type: String,
     pub signature: String,
     pub priority_fee: u32,
+    pub compute_units: Option<u32>,
+    pub bus: Option<u8>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
@@ -150,6 +231,24 @@ pub struct Reward {
     pub balance: u64,
 }
 
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
+#[diesel(table_name = crate::schema::signup_payments)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct InsertSignupPayment {
+    pub miner_pubkey: String,
+    pub signature: String,
+    pub amount: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
+#[diesel(table_name = crate::schema::stakes)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct InsertStake {
+    pub pool_id: i32,
+    pub txn_id: i32,
+    pub amount: u64,
+}
+
 #[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
 #[diesel(table_name = crate::schema::earnings)]
 #[diesel(check_for_backend(diesel::mysql::Mysql))]
@@ -168,4 +267,30 @@ pub struct InsertEarning {
     pub amount: u64,
 }
 
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
+#[diesel(table_name = crate::schema::referral_earnings)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct InsertReferralEarning {
+    pub referrer_id: i32,
+    pub referee_id: i32,
+    pub pool_id: i32,
+    pub challenge_id: i32,
+    pub amount: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, QueryableByName)]
+pub struct ReferralStats {
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub referee_count: i64,
+    #[diesel(sql_type = diesel::sql_types::Unsigned<diesel::sql_types::BigInt>)]
+    pub total_earned: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Queryable, Selectable, QueryableByName)]
+#[diesel(table_name = crate::schema::whitelist)]
+#[diesel(check_for_backend(diesel::mysql::Mysql))]
+pub struct WhitelistEntry {
+    pub id: i32,
+    pub pubkey: String,
+}
 
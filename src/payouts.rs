@@ -0,0 +1,458 @@
+use std::{str::FromStr, sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use serde::Serialize;
+use solana_sdk::{commitment_config::CommitmentConfig, compute_budget::ComputeBudgetInstruction, pubkey::Pubkey, signature::{Keypair, Signature}, signer::Signer, transaction::Transaction};
+use spl_associated_token_account::get_associated_token_address;
+use tokio::sync::{mpsc::UnboundedSender, Mutex};
+use tracing::{error, info};
+
+use crate::{app_database::{AppDatabase, AppDatabaseError}, ore_utils::{get_claim_ix, get_ore_mint, ORE_TOKEN_DECIMALS}, compute_units::ComputeUnitEstimator, rpc_pool::RpcPool, tx_manager::{status_outcome, TxManager, TxOutcome}, InsertClaim, InsertTxn};
+
+// Blockhashes expire after about 150 slots, claims sent this long before startup can't land anymore
+const RECONCILE_DELAY: Duration = Duration::from_secs(120);
+
+#[derive(Debug)]
+pub enum ClaimError {
+    InsufficientBalance,
+    RewardsFrozen,
+    FailedToGetBlockhash,
+    TransactionFailed(String),
+    Database(AppDatabaseError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClaimState {
+    Pending,
+    Confirmed,
+    Finalized,
+    Failed,
+}
+
+impl ClaimState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClaimState::Pending => "pending",
+            ClaimState::Confirmed => "confirmed",
+            ClaimState::Finalized => "finalized",
+            ClaimState::Failed => "failed",
+        }
+    }
+}
+
+pub struct ClaimSubmission {
+    pub claim_id: i32,
+    pub signature: Signature,
+}
+
+pub struct MessageInternalClaimSettled {
+    pub miner_id: i32,
+    pub claim_id: i32,
+    pub amount: u64,
+    pub signature: Signature,
+    pub state: ClaimState,
+    pub error: Option<String>,
+}
+
+/// Claims `amount` of the miner's rewards from the pool proof to the miner's payout address.
+/// Every claim path goes through here.
+///
+/// The miner's balance is reserved and the claim recorded as pending before the
+/// transaction is sent, so every sent claim has a row to settle. A background task then
+/// tracks it until it settles, refunding the balance if it fails, and reports the
+/// outcome on `claim_settled_sender`.
+pub async fn process_claim(
+    app_database: Arc<AppDatabase>,
+    rpc_pool: Arc<RpcPool>,
+    compute_unit_estimator: Arc<ComputeUnitEstimator>,
+    tx_manager: Arc<TxManager>,
+    wallet: Arc<Keypair>,
+    pool_id: i32,
+    miner_id: i32,
+    miner_pubkey: &str,
+    amount: u64,
+    txn_type: &str,
+    claim_settled_sender: UnboundedSender<MessageInternalClaimSettled>,
+) -> Result<ClaimSubmission, ClaimError> {
+    let miner = app_database.get_miner_by_pubkey_str(miner_pubkey.to_string()).await.map_err(ClaimError::Database)?;
+    if miner.rewards_frozen {
+        return Err(ClaimError::RewardsFrozen);
+    }
+
+    let user_pubkey = Pubkey::from_str(miner_pubkey).expect("Miner pubkey in database should be valid");
+    let payout_pubkey = match app_database.get_miner_payout_address(miner_pubkey.to_string()).await {
+        Ok(payout_address) => payout_address.payout_pubkey.and_then(|p| Pubkey::from_str(&p).ok()).unwrap_or(user_pubkey),
+        Err(e) => return Err(ClaimError::Database(e)),
+    };
+
+    let ore_mint = get_ore_mint();
+    let miner_token_account = get_associated_token_address(&payout_pubkey, &ore_mint);
+
+    let rpc_client = rpc_pool.client();
+    let mut ixs = Vec::new();
+    // let prio_fee_ix = ComputeBudgetInstruction::set_compute_unit_price(10000);
+    // ixs.push(prio_fee_ix);
+    if let Ok(response) = rpc_client.get_token_account_balance(&miner_token_account).await {
+            if let Some(_amount) = response.ui_amount {
+                info!("miner has valid token account.");
+            } else {
+                info!("will create token account for miner");
+                ixs.push(spl_associated_token_account::instruction::create_associated_token_account(
+                    &wallet.pubkey(),
+                    &payout_pubkey,
+                    &ore_api::consts::MINT_ADDRESS,
+                    &spl_token::id(),
+                ))
+            }
+
+    } else {
+        info!("Adding create ata ix for miner claim");
+        ixs.push(spl_associated_token_account::instruction::create_associated_token_account(
+            &wallet.pubkey(),
+            &payout_pubkey,
+            &ore_api::consts::MINT_ADDRESS,
+            &spl_token::id(),
+        ))
+    }
+
+    let ix = get_claim_ix(wallet.pubkey(), miner_token_account, amount);
+    ixs.push(ix);
+
+    let compute_units = compute_unit_estimator.estimate(&rpc_client, &wallet.pubkey(), &ixs).await;
+    if let Some(compute_units) = compute_units {
+        ixs.insert(0, ComputeBudgetInstruction::set_compute_unit_limit(compute_units.limit));
+    }
+
+    let hash = if let Ok((hash, _slot)) = rpc_client
+        .get_latest_blockhash_with_commitment(rpc_client.commitment()).await {
+        hash
+    } else {
+        return Err(ClaimError::FailedToGetBlockhash);
+    };
+
+    let mut tx = Transaction::new_with_payer(&ixs, Some(&wallet.pubkey()));
+
+    tx.sign(&[&wallet], hash);
+
+    // Reserve the balance before sending so concurrent claims can't overdraw it.
+    if app_database.decrease_miner_reward(miner_id, amount).await.is_err() {
+        return Err(ClaimError::InsufficientBalance);
+    }
+
+    let sig = tx.signatures[0];
+    // TODO: use transacions, or at least put them into one query
+    if let Err(e) = app_database.update_pool_claimed(wallet.pubkey().to_string(), amount).await {
+        let _ = app_database.update_miner_reward(miner_id, amount).await;
+        return Err(ClaimError::Database(e));
+    }
+
+    let itxn = InsertTxn {
+        txn_type: txn_type.to_string(),
+        signature: sig.to_string(),
+        priority_fee: 0,
+        compute_units: compute_units.map(|units| units.consumed),
+        bus: None,
+    };
+    let txn = match app_database.add_new_txn(itxn).await {
+        Ok(_) => app_database.get_txn_by_sig(sig.to_string()).await,
+        Err(e) => Err(e),
+    };
+    let txn = match txn {
+        Ok(txn) => txn,
+        Err(e) => {
+            let _ = app_database.update_miner_reward(miner_id, amount).await;
+            let _ = app_database.decrease_pool_claimed(wallet.pubkey().to_string(), amount).await;
+            return Err(ClaimError::Database(e));
+        }
+    };
+
+    let iclaim = InsertClaim {
+        miner_id,
+        pool_id,
+        txn_id: txn.id,
+        amount,
+        status: ClaimState::Pending.as_str().to_string(),
+    };
+    if let Err(e) = app_database.add_new_claim(iclaim).await {
+        let _ = app_database.update_miner_reward(miner_id, amount).await;
+        let _ = app_database.decrease_pool_claimed(wallet.pubkey().to_string(), amount).await;
+        return Err(ClaimError::Database(e));
+    }
+    let claim = match app_database.get_claim_status_by_sig(sig.to_string()).await {
+        Ok(claim) => claim,
+        Err(e) => {
+            // The pending row exists, the transaction was never sent so reconciling
+            // pending claims on the next startup refunds it.
+            error!("Claim {} was recorded but couldn't be read back, not sending it: {:?}", sig, e);
+            return Err(ClaimError::Database(e));
+        }
+    };
+
+    if let Err(e) = rpc_pool.send_transaction(&tx).await {
+        if let Some(err) = e.get_transaction_error() {
+            // rejected in preflight, it can't land
+            error!("Claim for {} failed to send: {}", miner_pubkey, err);
+            compute_unit_estimator.record_error(&tx, &err).await;
+            fail_claim(&app_database, wallet.pubkey(), claim.id, miner_id, amount, sig, TxOutcome::Failed(err.clone()).to_string(), &claim_settled_sender).await;
+            return Err(ClaimError::TransactionFailed(err.to_string()));
+        }
+        // the transaction may have gone out anyway, only its tracker can tell
+        error!("Claim for {} may not have been sent, tracking it: {:?}", miner_pubkey, e);
+    } else {
+        info!("Miner claim sent.\nSig: {}", sig.to_string());
+    }
+
+    let claim_id = claim.id;
+    tokio::spawn(async move {
+        track_claim(app_database, tx_manager, wallet.pubkey(), tx, claim_id, miner_id, amount, claim_settled_sender).await;
+    });
+
+    Ok(ClaimSubmission {
+        claim_id,
+        signature: sig,
+    })
+}
+
+/// Follows a sent claim until it finalizes, or gives the miner their balance back if
+/// it fails or its blockhash expires first.
+async fn track_claim(
+    app_database: Arc<AppDatabase>,
+    tx_manager: Arc<TxManager>,
+    pool_authority: Pubkey,
+    tx: Transaction,
+    claim_id: i32,
+    miner_id: i32,
+    amount: u64,
+    claim_settled_sender: UnboundedSender<MessageInternalClaimSettled>,
+) {
+    let sig = tx.signatures[0];
+    let report = tx_manager.track(&tx, CommitmentConfig::confirmed()).await;
+    if let TxOutcome::Unknown = report.outcome {
+        // it may still land, leave the claim pending rather than refund it
+        error!("Claim {} was seen but not confirmed, leaving it pending.", claim_id);
+        return;
+    }
+    if !report.landed() {
+        error!("Claim {} failed after {} rebroadcasts: {}", claim_id, report.sends, report.outcome);
+        fail_claim(&app_database, pool_authority, claim_id, miner_id, amount, sig, report.outcome.to_string(), &claim_settled_sender).await;
+        return;
+    }
+
+    let _ = app_database.update_claim_status(claim_id, ClaimState::Confirmed.as_str().to_string(), None).await;
+    let _ = claim_settled_sender.send(MessageInternalClaimSettled {
+        miner_id,
+        claim_id,
+        amount,
+        signature: sig,
+        state: ClaimState::Confirmed,
+        error: None,
+    });
+
+    // A confirmed claim is as good as settled, the balance isn't given back if finalizing times out
+    let report = tx_manager.track(&tx, CommitmentConfig::finalized()).await;
+    if report.landed() {
+        let _ = app_database.update_claim_status(claim_id, ClaimState::Finalized.as_str().to_string(), None).await;
+        info!("Claim {} finalized.", claim_id);
+    } else {
+        error!("Claim {} confirmed but didn't finalize: {}", claim_id, report.outcome);
+    }
+}
+
+/// Marks a claim failed and gives the miner their balance back.
+async fn fail_claim(
+    app_database: &AppDatabase,
+    pool_authority: Pubkey,
+    claim_id: i32,
+    miner_id: i32,
+    amount: u64,
+    signature: Signature,
+    error: String,
+    claim_settled_sender: &UnboundedSender<MessageInternalClaimSettled>,
+) {
+    let _ = app_database.update_claim_status(claim_id, ClaimState::Failed.as_str().to_string(), Some(error.clone())).await;
+    let _ = app_database.update_miner_reward(miner_id, amount).await;
+    let _ = app_database.decrease_pool_claimed(pool_authority.to_string(), amount).await;
+    let _ = claim_settled_sender.send(MessageInternalClaimSettled {
+        miner_id,
+        claim_id,
+        amount,
+        signature,
+        state: ClaimState::Failed,
+        error: Some(error),
+    });
+}
+
+/// Settles claims left pending by a previous run, whose trackers died with the process.
+/// Waits out `RECONCILE_DELAY` first so every claim sent before startup has an expired
+/// blockhash, then a claim missing from the status history can't land anymore.
+pub async fn reconcile_pending_claims(
+    app_database: Arc<AppDatabase>,
+    rpc_pool: Arc<RpcPool>,
+    pool_id: i32,
+    pool_authority: Pubkey,
+    claim_settled_sender: UnboundedSender<MessageInternalClaimSettled>,
+) {
+    let claims = match app_database.get_pending_claims(pool_id).await {
+        Ok(claims) => claims,
+        Err(e) => {
+            error!("Failed to load pending claims: {:?}", e);
+            return;
+        }
+    };
+    if claims.is_empty() {
+        return;
+    }
+
+    info!("Reconciling {} pending claims in {}s.", claims.len(), RECONCILE_DELAY.as_secs());
+    tokio::time::sleep(RECONCILE_DELAY).await;
+
+    for claim in claims {
+        let sig = match Signature::from_str(&claim.signature) {
+            Ok(sig) => sig,
+            Err(_) => {
+                error!("Pending claim {} has an invalid signature.", claim.id);
+                continue;
+            }
+        };
+
+        let status = match rpc_pool.client().get_signature_statuses_with_history(&[sig]).await {
+            Ok(response) => response.value[0].clone(),
+            Err(e) => {
+                error!("Failed to get status of pending claim {}: {}", claim.id, e);
+                continue;
+            }
+        };
+
+        match status {
+            None => {
+                error!("Pending claim {} never landed, refunding.", claim.id);
+                fail_claim(&app_database, pool_authority, claim.id, claim.miner_id, claim.amount, sig, TxOutcome::Expired.to_string(), &claim_settled_sender).await;
+            },
+            Some(status) => {
+                if let Some(TxOutcome::Failed(err)) = status_outcome(&status, CommitmentConfig::confirmed()) {
+                    error!("Pending claim {} failed: {}", claim.id, err);
+                    fail_claim(&app_database, pool_authority, claim.id, claim.miner_id, claim.amount, sig, TxOutcome::Failed(err).to_string(), &claim_settled_sender).await;
+                    continue;
+                }
+
+                let state = if status.satisfies_commitment(CommitmentConfig::finalized()) {
+                    ClaimState::Finalized
+                } else {
+                    ClaimState::Confirmed
+                };
+                info!("Pending claim {} landed, marking it {}.", claim.id, state.as_str());
+                let _ = app_database.update_claim_status(claim.id, state.as_str().to_string(), None).await;
+                let _ = claim_settled_sender.send(MessageInternalClaimSettled {
+                    miner_id: claim.miner_id,
+                    claim_id: claim.id,
+                    amount: claim.amount,
+                    signature: sig,
+                    state,
+                    error: None,
+                });
+            }
+        }
+    }
+}
+
+pub struct AutoPayoutConfig {
+    pub interval_secs: u64,
+    pub default_threshold: u64,
+    pub max_payouts_per_run: u64,
+    /// Total base units paid out per run, 0 for no limit.
+    pub max_amount_per_run: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PayoutReportEntry {
+    pub pubkey: String,
+    pub amount: u64,
+    pub claim_id: Option<i32>,
+    pub signature: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PayoutReport {
+    pub started_at: u64,
+    pub finished_at: u64,
+    pub total_paid: u64,
+    pub total_paid_dec: f64,
+    pub payouts: Vec<PayoutReportEntry>,
+}
+
+/// Periodically pays out every miner whose balance exceeds their payout threshold.
+pub async fn auto_payout_system(
+    config: AutoPayoutConfig,
+    pool_id: i32,
+    app_database: Arc<AppDatabase>,
+    rpc_pool: Arc<RpcPool>,
+    compute_unit_estimator: Arc<ComputeUnitEstimator>,
+    tx_manager: Arc<TxManager>,
+    wallet: Arc<Keypair>,
+    last_report: Arc<Mutex<Option<PayoutReport>>>,
+    claim_settled_sender: UnboundedSender<MessageInternalClaimSettled>,
+) {
+    loop {
+        tokio::time::sleep(Duration::from_secs(config.interval_secs)).await;
+
+        let candidates = match app_database.get_miners_for_auto_payout(pool_id, config.default_threshold, config.max_payouts_per_run).await {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                error!("Failed to load auto payout candidates: {:?}", e);
+                continue;
+            }
+        };
+
+        if candidates.is_empty() {
+            continue;
+        }
+
+        let started_at = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
+        let mut total_paid: u64 = 0;
+        let mut payouts = Vec::new();
+        for candidate in candidates {
+            if config.max_amount_per_run > 0 && total_paid.saturating_add(candidate.balance) > config.max_amount_per_run {
+                info!("Auto payout run reached its amount limit.");
+                break;
+            }
+
+            match process_claim(app_database.clone(), rpc_pool.clone(), compute_unit_estimator.clone(), tx_manager.clone(), wallet.clone(), pool_id, candidate.miner_id, &candidate.pubkey, candidate.balance, "auto_payout", claim_settled_sender.clone()).await {
+                Ok(submission) => {
+                    total_paid += candidate.balance;
+                    payouts.push(PayoutReportEntry {
+                        pubkey: candidate.pubkey,
+                        amount: candidate.balance,
+                        claim_id: Some(submission.claim_id),
+                        signature: Some(submission.signature.to_string()),
+                        error: None,
+                    });
+                },
+                Err(e) => {
+                    payouts.push(PayoutReportEntry {
+                        pubkey: candidate.pubkey,
+                        amount: candidate.balance,
+                        claim_id: None,
+                        signature: None,
+                        error: Some(format!("{:?}", e)),
+                    });
+                }
+            }
+        }
+
+        let finished_at = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs();
+        let report = PayoutReport {
+            started_at,
+            finished_at,
+            total_paid,
+            total_paid_dec: total_paid as f64 / 10f64.powf(ORE_TOKEN_DECIMALS as f64),
+            payouts,
+        };
+        info!(
+            "Auto payout run finished: {} sent, {} failed, {} ORE total.",
+            report.payouts.iter().filter(|p| p.signature.is_some()).count(),
+            report.payouts.iter().filter(|p| p.signature.is_none()).count(),
+            report.total_paid_dec,
+        );
+        *last_report.lock().await = Some(report);
+    }
+}
@@ -0,0 +1,204 @@
+use std::{collections::HashMap, hash::Hash, net::{IpAddr, SocketAddr}, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use axum::http::{HeaderMap, HeaderName};
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+
+pub struct RateLimitConfig {
+    pub ip_rate: f64,
+    pub ip_burst: f64,
+    pub pubkey_rate: f64,
+    pub pubkey_burst: f64,
+    /// Failures within `strike_window` before an ip is banned.
+    pub ban_threshold: u32,
+    pub strike_window: Duration,
+    pub ban_duration: Duration,
+    pub max_connections_per_ip: usize,
+    /// Header a trusted reverse proxy puts the client ip in, the peer address is used without it.
+    pub client_ip_header: Option<HeaderName>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RateLimitError {
+    Banned,
+    Limited,
+}
+
+struct TokenBucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(burst: f64) -> Self {
+        TokenBucket {
+            tokens: burst,
+            last_refill: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, rate: f64, burst: f64) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * rate).min(burst);
+        self.last_refill = now;
+    }
+
+    fn try_take(&mut self, rate: f64, burst: f64) -> bool {
+        self.refill(rate, burst);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+struct Strikes {
+    count: u32,
+    window_start: Instant,
+}
+
+struct Ban {
+    reason: String,
+    banned_at: u64,
+    until: Instant,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BanInfo {
+    pub ip: String,
+    pub reason: String,
+    /// Unix timestamp the ban started at.
+    pub banned_at: u64,
+    pub expires_in: u64,
+}
+
+pub struct RateLimiter {
+    config: RateLimitConfig,
+    ip_buckets: HashMap<IpAddr, TokenBucket>,
+    pubkey_buckets: HashMap<Pubkey, TokenBucket>,
+    strikes: HashMap<IpAddr, Strikes>,
+    bans: HashMap<IpAddr, Ban>,
+}
+
+impl RateLimiter {
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            ip_buckets: HashMap::new(),
+            pubkey_buckets: HashMap::new(),
+            strikes: HashMap::new(),
+            bans: HashMap::new(),
+        }
+    }
+
+    pub fn max_connections_per_ip(&self) -> usize {
+        self.config.max_connections_per_ip
+    }
+
+    /// Ip the limits apply to for a request from `peer`. The last address in the configured
+    /// header is used, earlier ones were supplied by the client and can't be trusted.
+    pub fn client_ip(&self, peer: &SocketAddr, headers: &HeaderMap) -> IpAddr {
+        self.config.client_ip_header.as_ref()
+            .and_then(|name| headers.get(name))
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok())
+            .unwrap_or(peer.ip())
+    }
+
+    pub fn is_banned(&self, ip: &IpAddr) -> bool {
+        self.bans.get(ip).map_or(false, |ban| ban.until > Instant::now())
+    }
+
+    pub fn check_ip(&mut self, ip: IpAddr) -> Result<(), RateLimitError> {
+        if self.is_banned(&ip) {
+            return Err(RateLimitError::Banned);
+        }
+        let (rate, burst) = (self.config.ip_rate, self.config.ip_burst);
+        let bucket = self.ip_buckets.entry(ip).or_insert_with(|| TokenBucket::new(burst));
+        if bucket.try_take(rate, burst) {
+            Ok(())
+        } else {
+            Err(RateLimitError::Limited)
+        }
+    }
+
+    pub fn check_pubkey(&mut self, pubkey: Pubkey) -> Result<(), RateLimitError> {
+        let (rate, burst) = (self.config.pubkey_rate, self.config.pubkey_burst);
+        let bucket = self.pubkey_buckets.entry(pubkey).or_insert_with(|| TokenBucket::new(burst));
+        if bucket.try_take(rate, burst) {
+            Ok(())
+        } else {
+            Err(RateLimitError::Limited)
+        }
+    }
+
+    /// Records a failed auth attempt or bad submission from `ip`.
+    /// Returns true if this failure got the ip banned.
+    pub fn record_failure(&mut self, ip: IpAddr, reason: &str) -> bool {
+        if self.is_banned(&ip) {
+            return false;
+        }
+
+        let now = Instant::now();
+        let strikes = self.strikes.entry(ip).or_insert(Strikes {
+            count: 0,
+            window_start: now,
+        });
+        if now.duration_since(strikes.window_start) > self.config.strike_window {
+            strikes.count = 0;
+            strikes.window_start = now;
+        }
+        strikes.count += 1;
+
+        if strikes.count < self.config.ban_threshold {
+            return false;
+        }
+
+        self.strikes.remove(&ip);
+        self.bans.insert(ip, Ban {
+            reason: reason.to_string(),
+            banned_at: SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_secs(),
+            until: now + self.config.ban_duration,
+        });
+        true
+    }
+
+    pub fn unban(&mut self, ip: &IpAddr) -> bool {
+        self.strikes.remove(ip);
+        self.bans.remove(ip).is_some()
+    }
+
+    pub fn bans(&self) -> Vec<BanInfo> {
+        let now = Instant::now();
+        self.bans.iter()
+            .filter(|(_, ban)| ban.until > now)
+            .map(|(ip, ban)| BanInfo {
+                ip: ip.to_string(),
+                reason: ban.reason.clone(),
+                banned_at: ban.banned_at,
+                expires_in: ban.until.duration_since(now).as_secs(),
+            })
+            .collect()
+    }
+
+    /// Forgets full buckets, stale strikes and expired bans so idle clients don't use memory.
+    pub fn prune(&mut self) {
+        let now = Instant::now();
+        prune_buckets(&mut self.ip_buckets, self.config.ip_rate, self.config.ip_burst);
+        prune_buckets(&mut self.pubkey_buckets, self.config.pubkey_rate, self.config.pubkey_burst);
+        let strike_window = self.config.strike_window;
+        self.strikes.retain(|_, s| now.duration_since(s.window_start) <= strike_window);
+        self.bans.retain(|_, b| b.until > now);
+    }
+}
+
+fn prune_buckets<K: Eq + Hash>(buckets: &mut HashMap<K, TokenBucket>, rate: f64, burst: f64) {
+    buckets.retain(|_, bucket| {
+        bucket.refill(rate, burst);
+        bucket.tokens < burst
+    });
+}
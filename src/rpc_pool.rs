@@ -0,0 +1,165 @@
+use std::{sync::{atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering}, Arc}, time::{Duration, Instant}};
+
+use futures::{future::join_all, stream::FuturesUnordered, StreamExt};
+use solana_client::{client_error::{ClientError, ClientErrorKind, Result as ClientResult}, nonblocking::rpc_client::RpcClient, rpc_config::RpcSendTransactionConfig};
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature, transaction::Transaction};
+use tracing::{error, info};
+
+// Endpoints this many slots behind the most up to date one aren't used for reads
+const MAX_SLOT_LAG: u64 = 20;
+
+struct RpcEndpoint {
+    client: Arc<RpcClient>,
+    healthy: AtomicBool,
+    slot: AtomicU64,
+    latency_ms: AtomicU64,
+    // transport errors seen while sending since the last successful health check
+    send_failures: AtomicU32,
+}
+
+impl RpcEndpoint {
+    fn is_usable(&self, max_slot: u64) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+            && max_slot.saturating_sub(self.slot.load(Ordering::Relaxed)) <= MAX_SLOT_LAG
+    }
+
+    fn record_send_error(&self, e: &ClientError) {
+        if matches!(e.kind(), ClientErrorKind::Io(_) | ClientErrorKind::Reqwest(_)) {
+            self.send_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A set of rpc endpoints. Reads go to the healthiest endpoint and transactions are
+/// broadcast to several at once, so one degraded provider doesn't take the pool down.
+pub struct RpcPool {
+    endpoints: Vec<Arc<RpcEndpoint>>,
+    ws_urls: Vec<String>,
+    broadcast_count: usize,
+}
+
+impl RpcPool {
+    pub fn new(urls: Vec<String>, ws_urls: Vec<String>, broadcast_count: usize) -> Self {
+        assert!(!urls.is_empty(), "at least one rpc url is required");
+        assert!(!ws_urls.is_empty(), "at least one rpc websocket url is required");
+        let endpoints = urls.into_iter().map(|url| {
+            Arc::new(RpcEndpoint {
+                client: Arc::new(RpcClient::new_with_commitment(url, CommitmentConfig::confirmed())),
+                healthy: AtomicBool::new(true),
+                slot: AtomicU64::new(0),
+                latency_ms: AtomicU64::new(0),
+                send_failures: AtomicU32::new(0),
+            })
+        }).collect();
+
+        RpcPool {
+            endpoints,
+            ws_urls,
+            broadcast_count: broadcast_count.max(1),
+        }
+    }
+
+    /// Endpoints ordered from healthiest to least healthy.
+    fn ranked(&self) -> Vec<Arc<RpcEndpoint>> {
+        let max_slot = self.endpoints.iter().map(|e| e.slot.load(Ordering::Relaxed)).max().unwrap_or(0);
+        let mut endpoints = self.endpoints.clone();
+        endpoints.sort_by_key(|e| (
+            !e.is_usable(max_slot),
+            e.send_failures.load(Ordering::Relaxed),
+            e.latency_ms.load(Ordering::Relaxed),
+        ));
+        endpoints
+    }
+
+    /// Client for the healthiest endpoint, for reads.
+    pub fn client(&self) -> Arc<RpcClient> {
+        self.ranked()[0].client.clone()
+    }
+
+    /// Websocket url to use for the `attempt`th pubsub connection, cycling through all of them.
+    pub fn ws_url(&self, attempt: usize) -> &str {
+        &self.ws_urls[attempt % self.ws_urls.len()]
+    }
+
+    /// Sends `tx` to the healthiest endpoints in parallel and returns as soon as one accepts it.
+    /// The other sends keep going in the background.
+    pub async fn send_transaction(&self, tx: &Transaction) -> ClientResult<Signature> {
+        let mut sends: FuturesUnordered<_> = self.ranked()
+            .into_iter()
+            .take(self.broadcast_count)
+            .map(|endpoint| {
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    let result = endpoint.client.send_transaction(&tx).await;
+                    if let Err(e) = &result {
+                        endpoint.record_send_error(e);
+                        error!("Failed to send transaction via {}: {}", endpoint.client.url(), e);
+                    }
+                    result
+                })
+            })
+            .collect();
+
+        let mut last_error = None;
+        while let Some(result) = sends.next().await {
+            match result {
+                Ok(Ok(sig)) => return Ok(sig),
+                Ok(Err(e)) => last_error = Some(e),
+                Err(e) => error!("Transaction send task failed: {}", e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| ClientErrorKind::Custom("no rpc endpoint accepted the transaction".to_string()).into()))
+    }
+
+    /// Resends `tx` to the healthiest endpoints without waiting on them. Preflight is
+    /// skipped since the transaction already passed it when first sent.
+    pub fn rebroadcast_transaction(&self, tx: &Transaction) {
+        for endpoint in self.ranked().into_iter().take(self.broadcast_count) {
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let config = RpcSendTransactionConfig {
+                    skip_preflight: true,
+                    ..Default::default()
+                };
+                if let Err(e) = endpoint.client.send_transaction_with_config(&tx, config).await {
+                    endpoint.record_send_error(&e);
+                }
+            });
+        }
+    }
+}
+
+/// Checks every endpoint's slot and latency each `interval`.
+pub async fn rpc_health_system(rpc_pool: Arc<RpcPool>, interval: Duration) {
+    let mut best_url = rpc_pool.client().url();
+    loop {
+        let checks = rpc_pool.endpoints.iter().map(|endpoint| async move {
+            let start = Instant::now();
+            let result = endpoint.client.get_slot().await;
+            (endpoint, result, start.elapsed())
+        });
+
+        for (endpoint, result, latency) in join_all(checks).await {
+            match result {
+                Ok(slot) => {
+                    endpoint.healthy.store(true, Ordering::Relaxed);
+                    endpoint.slot.store(slot, Ordering::Relaxed);
+                    endpoint.latency_ms.store(latency.as_millis() as u64, Ordering::Relaxed);
+                    endpoint.send_failures.store(0, Ordering::Relaxed);
+                },
+                Err(e) => {
+                    endpoint.healthy.store(false, Ordering::Relaxed);
+                    error!("Rpc health check failed for {}: {}", endpoint.client.url(), e);
+                }
+            }
+        }
+
+        let url = rpc_pool.client().url();
+        if url != best_url {
+            info!("Switched reads from rpc {} to {}", best_url, url);
+            best_url = url;
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
@@ -22,6 +22,10 @@ diesel::table! {
         amount -> Unsigned<Bigint>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        #[max_length = 12]
+        status -> Varchar,
+        #[max_length = 255]
+        error -> Nullable<Varchar>,
     }
 }
 
@@ -45,6 +49,19 @@ diesel::table! {
         enabled -> Bool,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        #[max_length = 44]
+        payout_pubkey -> Nullable<Varchar>,
+        #[max_length = 44]
+        pending_payout_pubkey -> Nullable<Varchar>,
+        pending_payout_available_at -> Nullable<Timestamp>,
+        payout_threshold -> Nullable<Unsigned<Bigint>>,
+        banned -> Bool,
+        #[max_length = 255]
+        status_reason -> Nullable<Varchar>,
+        rewards_frozen -> Bool,
+        #[max_length = 16]
+        referral_code -> Nullable<Varchar>,
+        referrer_id -> Nullable<Integer>,
     }
 }
 
@@ -59,6 +76,20 @@ diesel::table! {
         claimed_rewards -> Unsigned<Bigint>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        total_staked -> Unsigned<Bigint>,
+    }
+}
+
+diesel::table! {
+    referral_earnings (id) {
+        id -> Integer,
+        referrer_id -> Integer,
+        referee_id -> Integer,
+        pool_id -> Integer,
+        challenge_id -> Integer,
+        amount -> Unsigned<Bigint>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -73,6 +104,30 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    signup_payments (id) {
+        id -> Integer,
+        #[max_length = 44]
+        miner_pubkey -> Varchar,
+        #[max_length = 200]
+        signature -> Varchar,
+        amount -> Unsigned<Bigint>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
+diesel::table! {
+    stakes (id) {
+        id -> Integer,
+        pool_id -> Integer,
+        txn_id -> Integer,
+        amount -> Unsigned<Bigint>,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
+    }
+}
+
 diesel::table! {
     submissions (id) {
         id -> Integer,
@@ -84,6 +139,8 @@ diesel::table! {
         updated_at -> Timestamp,
         #[max_length = 16]
         digest -> Nullable<Binary>,
+        #[max_length = 32]
+        worker -> Varchar,
     }
 }
 
@@ -97,6 +154,18 @@ diesel::table! {
         priority_fee -> Unsigned<Integer>,
         created_at -> Timestamp,
         updated_at -> Timestamp,
+        compute_units -> Nullable<Unsigned<Integer>>,
+        bus -> Nullable<Unsigned<Tinyint>>,
+    }
+}
+
+diesel::table! {
+    whitelist (id) {
+        id -> Integer,
+        #[max_length = 44]
+        pubkey -> Varchar,
+        created_at -> Timestamp,
+        updated_at -> Timestamp,
     }
 }
 
@@ -106,7 +175,11 @@ diesel::allow_tables_to_appear_in_same_query!(
     earnings,
     miners,
     pools,
+    referral_earnings,
     rewards,
+    signup_payments,
+    stakes,
     submissions,
     txns,
+    whitelist,
 );
@@ -0,0 +1,447 @@
+use std::{collections::HashMap, net::IpAddr, time::{Duration, Instant}};
+
+use axum::http::StatusCode;
+use base64::{prelude::BASE64_STANDARD, Engine};
+use drillx::Solution;
+use rand::Rng;
+use serde::Deserialize;
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcTransactionConfig, rpc_request::RpcRequest};
+use solana_sdk::{commitment_config::CommitmentConfig, instruction::CompiledInstruction, message::{Message, VersionedMessage}, pubkey::Pubkey, signature::Signature, system_instruction::SystemInstruction, system_program, transaction::Transaction};
+use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding};
+
+// Prefixed to signup challenges so the signed bytes can never be a valid transaction message
+const SIGNUP_CHALLENGE_DOMAIN: &[u8] = b"ore-hq-server signup:";
+// Unanswered challenges across all clients, the oldest is dropped to make room
+const MAX_OUTSTANDING_SIGNUP_CHALLENGES: usize = 10_000;
+// Unanswered challenges per ip, so a few ips can't push out everyone else's
+const MAX_OUTSTANDING_SIGNUP_CHALLENGES_PER_IP: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SignupMode {
+    /// Users pay the signup cost with a SOL transfer.
+    Payment,
+    /// Users sign a server issued challenge, optionally with a proof of work.
+    Signature,
+    /// Users send the signup cost themselves and post the confirmed transaction signature.
+    OnChain,
+}
+
+impl SignupMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SignupMode::Payment => "payment",
+            SignupMode::Signature => "signature",
+            SignupMode::OnChain => "on-chain",
+        }
+    }
+}
+
+pub struct SignupChallenge {
+    pub pubkey: Pubkey,
+    pub ip: IpAddr,
+    pub challenge: [u8; 32],
+    pub issued_at: Instant,
+}
+
+impl SignupChallenge {
+    pub fn new(pubkey: Pubkey, ip: IpAddr) -> Self {
+        SignupChallenge {
+            pubkey,
+            ip,
+            challenge: rand::thread_rng().gen(),
+            issued_at: Instant::now(),
+        }
+    }
+
+    pub fn is_expired(&self, max_age: Duration) -> bool {
+        self.issued_at.elapsed() > max_age
+    }
+}
+
+/// Outstanding signup challenges, keyed by a random id handed to the caller so
+/// requesting a challenge for a pubkey never replaces one already issued for it.
+#[derive(Default)]
+pub struct SignupChallenges {
+    challenges: HashMap<u64, SignupChallenge>,
+}
+
+impl SignupChallenges {
+    /// Issues a challenge for `pubkey` to a client at `ip`, returning its id and bytes.
+    /// When `ip` or all clients together are at their limit the oldest challenge is dropped.
+    pub fn issue(&mut self, pubkey: Pubkey, ip: IpAddr, max_age: Duration) -> (u64, [u8; 32]) {
+        self.challenges.retain(|_, c| !c.is_expired(max_age));
+
+        let from_ip = self.challenges.values().filter(|c| c.ip == ip).count();
+        if from_ip >= MAX_OUTSTANDING_SIGNUP_CHALLENGES_PER_IP {
+            self.drop_oldest(Some(ip));
+        } else if self.challenges.len() >= MAX_OUTSTANDING_SIGNUP_CHALLENGES {
+            self.drop_oldest(None);
+        }
+
+        let mut rng = rand::thread_rng();
+        let mut id: u64 = rng.gen();
+        while self.challenges.contains_key(&id) {
+            id = rng.gen();
+        }
+        let challenge = SignupChallenge::new(pubkey, ip);
+        let bytes = challenge.challenge;
+        self.challenges.insert(id, challenge);
+        (id, bytes)
+    }
+
+    fn drop_oldest(&mut self, ip: Option<IpAddr>) {
+        let oldest = self.challenges.iter()
+            .filter(|(_, c)| ip.map_or(true, |ip| c.ip == ip))
+            .min_by_key(|(_, c)| c.issued_at)
+            .map(|(id, _)| *id);
+        if let Some(oldest) = oldest {
+            self.challenges.remove(&oldest);
+        }
+    }
+
+    /// Consumes the challenge `id` if it was issued for `pubkey`.
+    pub fn take(&mut self, id: u64, pubkey: &Pubkey) -> Option<SignupChallenge> {
+        if self.challenges.get(&id)?.pubkey != *pubkey {
+            return None;
+        }
+        self.challenges.remove(&id)
+    }
+}
+
+/// Bytes the user must sign to prove ownership of `user_pubkey`.
+pub fn signup_challenge_message(pool_authority: &Pubkey, user_pubkey: &Pubkey, challenge: &[u8; 32]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(SIGNUP_CHALLENGE_DOMAIN.len() + 96);
+    msg.extend_from_slice(SIGNUP_CHALLENGE_DOMAIN);
+    msg.extend_from_slice(&pool_authority.to_bytes());
+    msg.extend_from_slice(&user_pubkey.to_bytes());
+    msg.extend_from_slice(challenge);
+    msg
+}
+
+#[derive(Deserialize)]
+pub struct SignupProof {
+    /// Base58 signature over the challenge message.
+    pub signature: String,
+    /// Base64 drillx digest, required when the pool sets a proof of work difficulty.
+    pub digest: Option<String>,
+    pub nonce: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum SignupProofError {
+    InvalidBody,
+    MissingChallenge,
+    ChallengeExpired,
+    InvalidSignature,
+    MissingProofOfWork,
+    InvalidProofOfWork,
+    InsufficientDifficulty { required: u32, actual: u32 },
+}
+
+impl SignupProofError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SignupProofError::InvalidBody => "invalid_body",
+            SignupProofError::MissingChallenge => "missing_challenge",
+            SignupProofError::ChallengeExpired => "challenge_expired",
+            SignupProofError::InvalidSignature => "invalid_signature",
+            SignupProofError::MissingProofOfWork => "missing_proof_of_work",
+            SignupProofError::InvalidProofOfWork => "invalid_proof_of_work",
+            SignupProofError::InsufficientDifficulty { .. } => "insufficient_difficulty",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            SignupProofError::InvalidBody => "Body must be a json signup proof".to_string(),
+            SignupProofError::MissingChallenge => "No signup challenge with this id was issued for this pubkey".to_string(),
+            SignupProofError::ChallengeExpired => "Signup challenge has expired, request a new one".to_string(),
+            SignupProofError::InvalidSignature => "Signature does not match the challenge".to_string(),
+            SignupProofError::MissingProofOfWork => "A proof of work digest and nonce are required".to_string(),
+            SignupProofError::InvalidProofOfWork => "Proof of work is not valid for the challenge".to_string(),
+            SignupProofError::InsufficientDifficulty { required, actual } => format!("Proof of work difficulty must be at least {}, found {}", required, actual),
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            SignupProofError::InvalidSignature => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::json!({
+            "error": self.code(),
+            "message": self.message(),
+        }).to_string()
+    }
+}
+
+/// Verifies the user's signature over the issued challenge and, when `pow_difficulty` is
+/// non zero, a drillx solution for the same challenge.
+pub fn validate_signup_proof(
+    body: &str,
+    challenge: &SignupChallenge,
+    max_age: Duration,
+    pool_authority: &Pubkey,
+    user_pubkey: &Pubkey,
+    pow_difficulty: u32,
+) -> Result<(), SignupProofError> {
+    if challenge.is_expired(max_age) {
+        return Err(SignupProofError::ChallengeExpired);
+    }
+
+    let proof: SignupProof = serde_json::from_str(body).map_err(|_| SignupProofError::InvalidBody)?;
+    let signature = proof.signature.parse::<Signature>().map_err(|_| SignupProofError::InvalidSignature)?;
+    let msg = signup_challenge_message(pool_authority, user_pubkey, &challenge.challenge);
+    if !signature.verify(&user_pubkey.to_bytes(), &msg) {
+        return Err(SignupProofError::InvalidSignature);
+    }
+
+    if pow_difficulty > 0 {
+        let (digest, nonce) = match (proof.digest, proof.nonce) {
+            (Some(digest), Some(nonce)) => (digest, nonce),
+            _ => return Err(SignupProofError::MissingProofOfWork),
+        };
+        let digest: [u8; 16] = BASE64_STANDARD.decode(digest)
+            .ok()
+            .and_then(|d| d.try_into().ok())
+            .ok_or(SignupProofError::InvalidProofOfWork)?;
+
+        let solution = Solution::new(digest, nonce.to_le_bytes());
+        if !solution.is_valid(&challenge.challenge) {
+            return Err(SignupProofError::InvalidProofOfWork);
+        }
+        let difficulty = solution.to_hash().difficulty();
+        if difficulty < pow_difficulty {
+            return Err(SignupProofError::InsufficientDifficulty { required: pow_difficulty, actual: difficulty });
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum SignupPaymentError {
+    InvalidEncoding,
+    InvalidTransaction,
+    InvalidSignatures,
+    InvalidInstructionCount(usize),
+    WrongProgram(Pubkey),
+    NotATransfer,
+    InvalidAccounts,
+    WrongSource(Pubkey),
+    SourceNotSigner,
+    WrongDestination(Pubkey),
+    WrongAmount { expected: u64, actual: u64 },
+    SimulationFailed(String),
+    RpcFailed(String),
+    InvalidTransactionSignature,
+    TransactionNotFound,
+    TransactionFailed(String),
+    MissingTransactionMeta,
+    UnsupportedTransactionVersion,
+    SignatureAlreadyUsed,
+}
+
+impl SignupPaymentError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            SignupPaymentError::InvalidEncoding => "invalid_encoding",
+            SignupPaymentError::InvalidTransaction => "invalid_transaction",
+            SignupPaymentError::InvalidSignatures => "invalid_signatures",
+            SignupPaymentError::InvalidInstructionCount(_) => "invalid_instruction_count",
+            SignupPaymentError::WrongProgram(_) => "wrong_program",
+            SignupPaymentError::NotATransfer => "not_a_transfer",
+            SignupPaymentError::InvalidAccounts => "invalid_accounts",
+            SignupPaymentError::WrongSource(_) => "wrong_source",
+            SignupPaymentError::SourceNotSigner => "source_not_signer",
+            SignupPaymentError::WrongDestination(_) => "wrong_destination",
+            SignupPaymentError::WrongAmount { .. } => "wrong_amount",
+            SignupPaymentError::SimulationFailed(_) => "simulation_failed",
+            SignupPaymentError::RpcFailed(_) => "rpc_failed",
+            SignupPaymentError::InvalidTransactionSignature => "invalid_transaction_signature",
+            SignupPaymentError::TransactionNotFound => "transaction_not_found",
+            SignupPaymentError::TransactionFailed(_) => "transaction_failed",
+            SignupPaymentError::MissingTransactionMeta => "missing_transaction_meta",
+            SignupPaymentError::UnsupportedTransactionVersion => "unsupported_transaction_version",
+            SignupPaymentError::SignatureAlreadyUsed => "signature_already_used",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            SignupPaymentError::InvalidEncoding => "Transaction is not valid base64".to_string(),
+            SignupPaymentError::InvalidTransaction => "Failed to deserialize transaction".to_string(),
+            SignupPaymentError::InvalidSignatures => "Transaction is missing a valid signature".to_string(),
+            SignupPaymentError::InvalidInstructionCount(count) => format!("Expected exactly 1 instruction, found {}", count),
+            SignupPaymentError::WrongProgram(program_id) => format!("Instruction must use the system program, found {}", program_id),
+            SignupPaymentError::NotATransfer => "Instruction is not a system transfer".to_string(),
+            SignupPaymentError::InvalidAccounts => "Transfer instruction has invalid accounts".to_string(),
+            SignupPaymentError::WrongSource(source) => format!("Transfer must be sent from the signing up pubkey, found {}", source),
+            SignupPaymentError::SourceNotSigner => "Transfer source must sign the transaction".to_string(),
+            SignupPaymentError::WrongDestination(destination) => format!("Transfer must be sent to the pool wallet, found {}", destination),
+            SignupPaymentError::WrongAmount { expected, actual } => format!("Transfer must be {} lamports, found {}", expected, actual),
+            SignupPaymentError::SimulationFailed(err) => format!("Transaction simulation failed: {}", err),
+            SignupPaymentError::RpcFailed(err) => format!("Rpc request failed: {}", err),
+            SignupPaymentError::InvalidTransactionSignature => "Body must be a base58 transaction signature".to_string(),
+            SignupPaymentError::TransactionNotFound => "Transaction not found or not yet confirmed".to_string(),
+            SignupPaymentError::TransactionFailed(err) => format!("Transaction failed on-chain: {}", err),
+            SignupPaymentError::MissingTransactionMeta => "Rpc returned the transaction without its status, it can't be verified".to_string(),
+            SignupPaymentError::UnsupportedTransactionVersion => "Only legacy transactions are supported".to_string(),
+            SignupPaymentError::SignatureAlreadyUsed => "Transaction was already used for a signup".to_string(),
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            SignupPaymentError::RpcFailed(_) | SignupPaymentError::MissingTransactionMeta => StatusCode::INTERNAL_SERVER_ERROR,
+            SignupPaymentError::TransactionNotFound => StatusCode::NOT_FOUND,
+            SignupPaymentError::SignatureAlreadyUsed => StatusCode::CONFLICT,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::json!({
+            "error": self.code(),
+            "message": self.message(),
+        }).to_string()
+    }
+}
+
+pub fn decode_signup_transaction(body: &str) -> Result<Transaction, SignupPaymentError> {
+    let serialized_tx = BASE64_STANDARD.decode(body.trim()).map_err(|_| SignupPaymentError::InvalidEncoding)?;
+    bincode::deserialize(&serialized_tx).map_err(|_| SignupPaymentError::InvalidTransaction)
+}
+
+/// Checks that `message` is a single system transfer of exactly `signup_cost` lamports
+/// from the signing `user_pubkey` to `pool_wallet`.
+pub fn validate_signup_payment_message(message: &Message, user_pubkey: &Pubkey, pool_wallet: &Pubkey, signup_cost: u64) -> Result<(), SignupPaymentError> {
+    if message.instructions.len() != 1 {
+        return Err(SignupPaymentError::InvalidInstructionCount(message.instructions.len()));
+    }
+    validate_signup_transfer(message, &message.instructions[0], user_pubkey, pool_wallet, signup_cost)
+}
+
+/// Checks that `ix` in `message` is a system transfer of exactly `signup_cost` lamports
+/// from the user, who signed it, to the pool wallet.
+fn validate_signup_transfer(message: &Message, ix: &CompiledInstruction, user_pubkey: &Pubkey, pool_wallet: &Pubkey, signup_cost: u64) -> Result<(), SignupPaymentError> {
+    let program_id = message.account_keys.get(ix.program_id_index as usize).ok_or(SignupPaymentError::InvalidAccounts)?;
+    if *program_id != system_program::id() {
+        return Err(SignupPaymentError::WrongProgram(*program_id));
+    }
+
+    let lamports = match bincode::deserialize::<SystemInstruction>(&ix.data) {
+        Ok(SystemInstruction::Transfer { lamports }) => lamports,
+        _ => return Err(SignupPaymentError::NotATransfer),
+    };
+
+    if ix.accounts.len() != 2 {
+        return Err(SignupPaymentError::InvalidAccounts);
+    }
+
+    let source_index = ix.accounts[0] as usize;
+    let source = message.account_keys.get(source_index).ok_or(SignupPaymentError::InvalidAccounts)?;
+    if source != user_pubkey {
+        return Err(SignupPaymentError::WrongSource(*source));
+    }
+    if !message.is_signer(source_index) {
+        return Err(SignupPaymentError::SourceNotSigner);
+    }
+
+    let destination = message.account_keys.get(ix.accounts[1] as usize).ok_or(SignupPaymentError::InvalidAccounts)?;
+    if destination != pool_wallet {
+        return Err(SignupPaymentError::WrongDestination(*destination));
+    }
+
+    if lamports != signup_cost {
+        return Err(SignupPaymentError::WrongAmount { expected: signup_cost, actual: lamports });
+    }
+
+    Ok(())
+}
+
+/// Validates a client built signup payment and simulates it before it's sent.
+pub async fn validate_signup_payment(
+    rpc_client: &RpcClient,
+    tx: &Transaction,
+    user_pubkey: &Pubkey,
+    pool_wallet: &Pubkey,
+    signup_cost: u64,
+) -> Result<(), SignupPaymentError> {
+    if !tx.is_signed() || tx.verify().is_err() {
+        return Err(SignupPaymentError::InvalidSignatures);
+    }
+
+    validate_signup_payment_message(&tx.message, user_pubkey, pool_wallet, signup_cost)?;
+
+    match rpc_client.simulate_transaction(tx).await {
+        Ok(response) => {
+            if let Some(err) = response.value.err {
+                return Err(SignupPaymentError::SimulationFailed(err.to_string()));
+            }
+        },
+        Err(e) => {
+            return Err(SignupPaymentError::RpcFailed(e.to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn decode_signup_signature(body: &str) -> Result<Signature, SignupPaymentError> {
+    body.trim().parse::<Signature>().map_err(|_| SignupPaymentError::InvalidTransactionSignature)
+}
+
+/// Fetches a signup payment the user already sent and checks it landed without error
+/// and pays exactly `signup_cost` from `user_pubkey` to `pool_wallet`.
+pub async fn verify_onchain_signup_payment(
+    rpc_client: &RpcClient,
+    signature: &Signature,
+    user_pubkey: &Pubkey,
+    pool_wallet: &Pubkey,
+    signup_cost: u64,
+) -> Result<(), SignupPaymentError> {
+    let config = RpcTransactionConfig {
+        encoding: Some(UiTransactionEncoding::Base64),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    };
+
+    // Requested directly so an unknown or unconfirmed signature comes back as None instead of a decode error
+    let confirmed_tx = match rpc_client
+        .send::<Option<EncodedConfirmedTransactionWithStatusMeta>>(RpcRequest::GetTransaction, serde_json::json!([signature.to_string(), config]))
+        .await {
+        Ok(Some(confirmed_tx)) => confirmed_tx,
+        Ok(None) => return Err(SignupPaymentError::TransactionNotFound),
+        Err(e) => return Err(SignupPaymentError::RpcFailed(e.to_string())),
+    };
+
+    let meta = confirmed_tx.transaction.meta.as_ref().ok_or(SignupPaymentError::MissingTransactionMeta)?;
+    if let Some(err) = &meta.err {
+        return Err(SignupPaymentError::TransactionFailed(err.to_string()));
+    }
+
+    let tx = confirmed_tx.transaction.transaction.decode().ok_or(SignupPaymentError::InvalidTransaction)?;
+    let message = match tx.message {
+        VersionedMessage::Legacy(message) => message,
+        VersionedMessage::V0(_) => return Err(SignupPaymentError::UnsupportedTransactionVersion),
+    };
+
+    // wallets add compute budget instructions, so any one instruction can be the payment
+    let mut error = None;
+    for ix in &message.instructions {
+        match validate_signup_transfer(&message, ix, user_pubkey, pool_wallet, signup_cost) {
+            Ok(()) => return Ok(()),
+            Err(e) => {
+                // a transfer that doesn't match says more than an unrelated instruction
+                if error.is_none() || !matches!(e, SignupPaymentError::WrongProgram(_)) {
+                    error = Some(e);
+                }
+            }
+        }
+    }
+    Err(error.unwrap_or(SignupPaymentError::InvalidInstructionCount(0)))
+}
@@ -0,0 +1,153 @@
+//! Auto staking of the pool's own ORE into the pool proof.
+//!
+//! The amount follows the pool's accrued, unclaimed ORE: what the proof and the pool
+//! wallet hold, minus outstanding miner rewards, the configured reserve and what was
+//! already staked. The tokens themselves come from the pool wallet's token account,
+//! because the stake instruction transfers from a token account and claiming proof ORE
+//! only to stake it straight back would leave the proof where it started.
+
+use std::{sync::Arc, time::Duration};
+
+use ore_api::state::Proof;
+use solana_sdk::{compute_budget::ComputeBudgetInstruction, signature::Keypair, signer::Signer, transaction::Transaction};
+use spl_associated_token_account::get_associated_token_address;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::{app_database::AppDatabase, ore_utils::{get_ore_mint, get_stake_ix, ORE_TOKEN_DECIMALS}, compute_units::ComputeUnitEstimator, rpc_pool::RpcPool, tx_manager::{TxManager, TxOutcome}, InsertStake, InsertTxn};
+
+pub struct StakeConfig {
+    pub interval_secs: u64,
+    /// Percent of the pool's unstaked surplus to stake each run.
+    pub stake_percent: u64,
+    /// Base units kept unstaked on top of outstanding miner rewards.
+    pub reserve: u64,
+    pub min_stake_amount: u64,
+}
+
+/// Amount to stake from the pool wallet this run.
+///
+/// Outstanding miner rewards plus the reserve are never counted as surplus, and ORE that
+/// was already staked isn't counted again. Only what the wallet holds can be staked.
+pub fn get_stake_amount(config: &StakeConfig, proof_balance: u64, wallet_balance: u64, outstanding_rewards: u64, total_staked: u64) -> u64 {
+    let surplus = proof_balance
+        .saturating_add(wallet_balance)
+        .saturating_sub(outstanding_rewards)
+        .saturating_sub(config.reserve)
+        .saturating_sub(total_staked);
+
+    let amount = ((surplus as u128)
+        .saturating_mul(config.stake_percent.min(100) as u128)
+        .saturating_div(100) as u64)
+        .min(wallet_balance);
+
+    if amount < config.min_stake_amount {
+        0
+    } else {
+        amount
+    }
+}
+
+/// Periodically stakes a fraction of the pool's accrued, unclaimed ORE into the pool proof.
+pub async fn auto_stake_system(
+    config: StakeConfig,
+    pool_id: i32,
+    app_database: Arc<AppDatabase>,
+    rpc_pool: Arc<RpcPool>,
+    compute_unit_estimator: Arc<ComputeUnitEstimator>,
+    tx_manager: Arc<TxManager>,
+    wallet: Arc<Keypair>,
+    proof: Arc<Mutex<Proof>>,
+) {
+    let pool_token_account = get_associated_token_address(&wallet.pubkey(), &get_ore_mint());
+    loop {
+        tokio::time::sleep(Duration::from_secs(config.interval_secs)).await;
+
+        let proof_balance = {
+            proof.lock().await.balance
+        };
+
+        let outstanding_rewards = match app_database.get_pool_outstanding_rewards(pool_id).await {
+            Ok(rewards) => rewards.balance,
+            Err(e) => {
+                error!("Failed to load outstanding rewards: {:?}", e);
+                continue;
+            }
+        };
+
+        let db_pool = match app_database.get_pool_by_authority_pubkey(wallet.pubkey().to_string()).await {
+            Ok(db_pool) => db_pool,
+            Err(e) => {
+                error!("Failed to load pool: {:?}", e);
+                continue;
+            }
+        };
+
+        let rpc_client = rpc_pool.client();
+        // a missing token account just means the pool wallet holds no ORE
+        let wallet_balance = match rpc_client.get_token_account_balance(&pool_token_account).await {
+            Ok(balance) => balance.amount.parse::<u64>().unwrap_or(0),
+            Err(_) => 0,
+        };
+
+        let amount = get_stake_amount(&config, proof_balance, wallet_balance, outstanding_rewards, db_pool.total_staked);
+        if amount == 0 {
+            continue;
+        }
+
+        let decimals = 10f64.powf(ORE_TOKEN_DECIMALS as f64);
+        info!(
+            "Staking {} ORE. Proof balance: {} ORE, pool wallet: {} ORE, outstanding rewards: {} ORE, already staked: {} ORE",
+            amount as f64 / decimals,
+            proof_balance as f64 / decimals,
+            wallet_balance as f64 / decimals,
+            outstanding_rewards as f64 / decimals,
+            db_pool.total_staked as f64 / decimals,
+        );
+
+        let mut ixs = vec![get_stake_ix(wallet.pubkey(), pool_token_account, amount)];
+
+        let compute_units = compute_unit_estimator.estimate(&rpc_client, &wallet.pubkey(), &ixs).await;
+        if let Some(compute_units) = compute_units {
+            ixs.insert(0, ComputeBudgetInstruction::set_compute_unit_limit(compute_units.limit));
+        }
+
+        if let Ok((hash, _slot)) = rpc_client
+            .get_latest_blockhash_with_commitment(rpc_client.commitment()).await {
+            let mut tx = Transaction::new_with_payer(&ixs, Some(&wallet.pubkey()));
+
+            tx.sign(&[&wallet], hash);
+
+            let report = tx_manager.send(&tx, rpc_client.commitment()).await;
+
+            match report.outcome {
+                TxOutcome::Landed { .. } => {
+                    let sig = report.signature;
+                    info!("Pool stake succeeded.\nSig: {}", sig.to_string());
+                    let itxn = InsertTxn {
+                        txn_type: "stake".to_string(),
+                        signature: sig.to_string(),
+                        priority_fee: 0,
+                        compute_units: compute_units.map(|units| units.consumed),
+                        bus: None,
+                    };
+                    let _ = app_database.add_new_txn(itxn).await;
+                    let _ = app_database.update_pool_staked(wallet.pubkey().to_string(), amount).await;
+                    if let Ok(ntxn) = app_database.get_txn_by_sig(sig.to_string()).await {
+                        let istake = InsertStake {
+                            pool_id,
+                            txn_id: ntxn.id,
+                            amount,
+                        };
+                        let _ = app_database.add_new_stake(istake).await;
+                    }
+                },
+                outcome => {
+                    error!("Pool stake {} after {} sends: {}", report.signature, report.sends, outcome);
+                }
+            }
+        } else {
+            error!("Failed to get latest blockhash for pool stake.");
+        }
+    }
+}
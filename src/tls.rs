@@ -0,0 +1,34 @@
+use std::{path::{Path, PathBuf}, time::{Duration, SystemTime}};
+
+use axum_server::tls_rustls::RustlsConfig;
+use tracing::{error, info};
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// Polls the cert and key files every `interval` and swaps them into `config` when
+/// either changes, so renewed certificates are served without a restart.
+/// Existing connections keep the certificate they were opened with.
+pub async fn tls_reload_system(config: RustlsConfig, cert_path: PathBuf, key_path: PathBuf, interval: Duration) {
+    let mut last_modified = (modified_at(&cert_path), modified_at(&key_path));
+    loop {
+        tokio::time::sleep(interval).await;
+
+        let modified = (modified_at(&cert_path), modified_at(&key_path));
+        if modified == last_modified {
+            continue;
+        }
+
+        // renewals can write the cert and key separately, a failed reload is retried next tick
+        match config.reload_from_pem_file(&cert_path, &key_path).await {
+            Ok(()) => {
+                info!("Reloaded TLS certificate from {}", cert_path.display());
+                last_modified = modified;
+            },
+            Err(e) => {
+                error!("Failed to reload TLS certificate, keeping the current one: {}", e);
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use std::{fmt, sync::Arc, time::{Duration, Instant}};
+
+use solana_sdk::{commitment_config::CommitmentConfig, signature::Signature, transaction::{Transaction, TransactionError}};
+use solana_transaction_status::TransactionStatus;
+use tokio::task::JoinHandle;
+use tracing::error;
+
+use crate::{compute_units::ComputeUnitEstimator, rpc_pool::RpcPool};
+
+// Stop following a transaction that was seen but hasn't reached the requested commitment after this long
+const MAX_TRACK_TIME: Duration = Duration::from_secs(120);
+// Polls between checks of whether an unseen transaction's blockhash has expired
+const BLOCKHASH_CHECK_POLLS: u32 = 5;
+
+#[derive(Debug)]
+pub enum TxOutcome {
+    /// Reached the requested commitment.
+    Landed { slot: u64 },
+    /// The transaction returned an error, on chain or when simulated before sending.
+    Failed(TransactionError),
+    /// The blockhash expired before the transaction was seen. It's safe to re-sign
+    /// with a new blockhash and send again.
+    Expired,
+    /// The transaction was seen but didn't reach the requested commitment in time.
+    /// It may still land, so it must not be treated as failed.
+    Unknown,
+    /// No rpc endpoint accepted the transaction.
+    Rejected(String),
+}
+
+impl fmt::Display for TxOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxOutcome::Landed { slot } => write!(f, "landed in slot {}", slot),
+            TxOutcome::Failed(e) => write!(f, "failed: {}", e),
+            TxOutcome::Expired => write!(f, "blockhash expired before the transaction landed"),
+            TxOutcome::Unknown => write!(f, "seen but not confirmed in time"),
+            TxOutcome::Rejected(e) => write!(f, "rejected: {}", e),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TxReport {
+    pub signature: Signature,
+    pub outcome: TxOutcome,
+    /// Times the transaction was sent, including rebroadcasts.
+    pub sends: u32,
+    pub elapsed: Duration,
+}
+
+impl TxReport {
+    pub fn landed(&self) -> bool {
+        matches!(self.outcome, TxOutcome::Landed { .. })
+    }
+}
+
+/// Outcome of a transaction that has a status, or None if it hasn't reached `commitment` yet.
+pub fn status_outcome(status: &TransactionStatus, commitment: CommitmentConfig) -> Option<TxOutcome> {
+    if let Some(err) = &status.err {
+        Some(TxOutcome::Failed(err.clone()))
+    } else if status.satisfies_commitment(commitment) {
+        Some(TxOutcome::Landed { slot: status.slot })
+    } else {
+        None
+    }
+}
+
+/// Sends signed transactions and follows them until they land or their blockhash expires,
+/// rebroadcasting the same signed transaction while it hasn't been seen.
+pub struct TxManager {
+    rpc_pool: Arc<RpcPool>,
+    // told about failures so transactions that ran out of compute get a higher limit next time
+    compute_unit_estimator: Arc<ComputeUnitEstimator>,
+    rebroadcast_interval: Duration,
+}
+
+impl TxManager {
+    pub fn new(rpc_pool: Arc<RpcPool>, compute_unit_estimator: Arc<ComputeUnitEstimator>, rebroadcast_interval: Duration) -> Self {
+        TxManager {
+            rpc_pool,
+            compute_unit_estimator,
+            rebroadcast_interval,
+        }
+    }
+
+    pub async fn send(&self, tx: &Transaction, commitment: CommitmentConfig) -> TxReport {
+        let start = Instant::now();
+        if let Err(e) = self.rpc_pool.send_transaction(tx).await {
+            let outcome = if let Some(err) = e.get_transaction_error() {
+                self.compute_unit_estimator.record_error(tx, &err).await;
+                TxOutcome::Failed(err)
+            } else {
+                TxOutcome::Rejected(e.to_string())
+            };
+            return TxReport {
+                signature: tx.signatures[0],
+                outcome,
+                sends: 1,
+                elapsed: start.elapsed(),
+            };
+        }
+
+        let mut report = self.track(tx, commitment).await;
+        report.sends += 1;
+        report.elapsed = start.elapsed();
+        report
+    }
+
+    /// Sends `tx` from a background task so the caller can keep working until it's reported.
+    pub fn spawn(self: &Arc<Self>, tx: Transaction, commitment: CommitmentConfig) -> JoinHandle<TxReport> {
+        let tx_manager = self.clone();
+        tokio::spawn(async move {
+            tx_manager.send(&tx, commitment).await
+        })
+    }
+
+    /// Follows a transaction that was already sent once.
+    pub async fn track(&self, tx: &Transaction, commitment: CommitmentConfig) -> TxReport {
+        let signature = tx.signatures[0];
+        let start = Instant::now();
+        let mut sends = 0;
+        let mut polls = 0;
+        let mut seen = false;
+        let outcome = loop {
+            tokio::time::sleep(self.rebroadcast_interval).await;
+            if seen && start.elapsed() > MAX_TRACK_TIME {
+                break TxOutcome::Unknown;
+            }
+            polls += 1;
+
+            let rpc_client = self.rpc_pool.client();
+            match rpc_client.get_signature_statuses(&[signature]).await {
+                Ok(response) => {
+                    if let Some(status) = &response.value[0] {
+                        if let Some(outcome) = status_outcome(status, commitment) {
+                            break outcome;
+                        }
+                        // seen, just not at the requested commitment yet
+                        seen = true;
+                        continue;
+                    }
+                },
+                Err(e) => {
+                    error!("Failed to get status of {}: {}", signature, e);
+                }
+            }
+
+            // once seen, a missing status is more likely a lagging endpoint than a dropped transaction
+            if seen {
+                continue;
+            }
+
+            if polls % BLOCKHASH_CHECK_POLLS == 0 {
+                if let Ok(false) = rpc_client.is_blockhash_valid(&tx.message.recent_blockhash, CommitmentConfig::processed()).await {
+                    // it could have landed between the status poll and the blockhash check
+                    match rpc_client.get_signature_statuses_with_history(&[signature]).await {
+                        Ok(response) => {
+                            match &response.value[0] {
+                                None => break TxOutcome::Expired,
+                                Some(status) => {
+                                    if let Some(outcome) = status_outcome(status, commitment) {
+                                        break outcome;
+                                    }
+                                    seen = true;
+                                },
+                            }
+                        },
+                        Err(e) => {
+                            error!("Failed to get status history of {}: {}", signature, e);
+                        }
+                    }
+                    // an expired blockhash can't be rebroadcast
+                    continue;
+                }
+            }
+
+            self.rpc_pool.rebroadcast_transaction(tx);
+            sends += 1;
+        };
+
+        if let TxOutcome::Failed(err) = &outcome {
+            self.compute_unit_estimator.record_error(tx, err).await;
+        }
+
+        TxReport {
+            signature,
+            outcome,
+            sends,
+            elapsed: start.elapsed(),
+        }
+    }
+}
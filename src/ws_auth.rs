@@ -0,0 +1,143 @@
+use std::{collections::HashMap, net::IpAddr, time::{Duration, Instant}};
+
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use rand::Rng;
+use solana_sdk::pubkey::Pubkey;
+
+// Unanswered nonces are dropped after this many seconds
+pub const WS_AUTH_NONCE_MAX_AGE: u64 = 60;
+// Nonces waiting to be answered across all clients, the oldest is dropped to make room
+const MAX_OUTSTANDING_NONCES: usize = 10_000;
+// Nonces waiting to be answered per ip, so a few ips can't push out everyone else's
+const MAX_OUTSTANDING_NONCES_PER_IP: usize = 16;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum WsAuthError {
+    InvalidNonce,
+    UnknownNonce,
+    NonceExpired,
+    WrongPubkey,
+}
+
+impl WsAuthError {
+    pub fn message(&self) -> &'static str {
+        match self {
+            WsAuthError::InvalidNonce => "Invalid nonce",
+            WsAuthError::UnknownNonce => "Unknown or already used nonce",
+            WsAuthError::NonceExpired => "Nonce expired",
+            WsAuthError::WrongPubkey => "Nonce was issued for another pubkey",
+        }
+    }
+}
+
+struct IssuedNonce {
+    pubkey: Pubkey,
+    ip: IpAddr,
+    issued_at: Instant,
+}
+
+pub struct Session {
+    pub pubkey: Pubkey,
+    pub expires_at: Instant,
+}
+
+/// Server side state for websocket authentication: single use nonces handed out
+/// by the nonce endpoint and single use session tokens for quick reconnects.
+#[derive(Default)]
+pub struct WsAuth {
+    nonces: HashMap<[u8; 32], IssuedNonce>,
+    sessions: HashMap<String, Session>,
+}
+
+impl WsAuth {
+    /// Issues a nonce for `pubkey` to a client at `ip`. When `ip` or all clients together
+    /// are at their limit the oldest nonce is dropped, a client that waited that long to
+    /// answer it can ask for another.
+    pub fn issue_nonce(&mut self, pubkey: Pubkey, ip: IpAddr) -> [u8; 32] {
+        let max_age = Duration::from_secs(WS_AUTH_NONCE_MAX_AGE);
+        self.nonces.retain(|_, n| n.issued_at.elapsed() <= max_age);
+
+        let from_ip = self.nonces.values().filter(|n| n.ip == ip).count();
+        if from_ip >= MAX_OUTSTANDING_NONCES_PER_IP {
+            self.drop_oldest_nonce(Some(ip));
+        } else if self.nonces.len() >= MAX_OUTSTANDING_NONCES {
+            self.drop_oldest_nonce(None);
+        }
+
+        let nonce: [u8; 32] = rand::thread_rng().gen();
+        self.nonces.insert(nonce, IssuedNonce {
+            pubkey,
+            ip,
+            issued_at: Instant::now(),
+        });
+        nonce
+    }
+
+    fn drop_oldest_nonce(&mut self, ip: Option<IpAddr>) {
+        let oldest = self.nonces.iter()
+            .filter(|(_, n)| ip.map_or(true, |ip| n.ip == ip))
+            .min_by_key(|(_, n)| n.issued_at)
+            .map(|(nonce, _)| *nonce);
+        if let Some(oldest) = oldest {
+            self.nonces.remove(&oldest);
+        }
+    }
+
+    /// Consumes `nonce`, it can't be used again whether or not it was valid for `pubkey`.
+    pub fn take_nonce(&mut self, nonce: &[u8; 32], pubkey: &Pubkey) -> Result<(), WsAuthError> {
+        let issued = self.nonces.remove(nonce).ok_or(WsAuthError::UnknownNonce)?;
+        if issued.issued_at.elapsed() > Duration::from_secs(WS_AUTH_NONCE_MAX_AGE) {
+            return Err(WsAuthError::NonceExpired);
+        }
+        if issued.pubkey != *pubkey {
+            return Err(WsAuthError::WrongPubkey);
+        }
+        Ok(())
+    }
+
+    /// Issues a token for `pubkey` that's valid until `expires_at`.
+    pub fn issue_session(&mut self, pubkey: Pubkey, expires_at: Instant) -> String {
+        let now = Instant::now();
+        self.sessions.retain(|_, s| s.expires_at > now);
+
+        let token_bytes: [u8; 32] = rand::thread_rng().gen();
+        let token = BASE64_URL_SAFE_NO_PAD.encode(token_bytes);
+        self.sessions.insert(token.clone(), Session {
+            pubkey,
+            expires_at,
+        });
+        token
+    }
+
+    /// Consumes a session token. The connection it opens gets a new token with the same
+    /// expiry, so a session can't be stretched past the ttl it was first issued with.
+    pub fn take_session(&mut self, token: &str) -> Option<Session> {
+        self.sessions.remove(token).filter(|s| s.expires_at > Instant::now())
+    }
+}
+
+pub fn decode_ws_nonce(nonce: &str) -> Result<[u8; 32], WsAuthError> {
+    BASE64_URL_SAFE_NO_PAD.decode(nonce)
+        .ok()
+        .and_then(|n| n.try_into().ok())
+        .ok_or(WsAuthError::InvalidNonce)
+}
+
+pub fn encode_ws_nonce(nonce: &[u8; 32]) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(nonce)
+}
+
+/// Bytes a miner signs to open a websocket connection.
+pub fn ws_auth_message(nonce: &[u8; 32], timestamp: u64, pool_authority: &Pubkey) -> [u8; 72] {
+    let mut msg = [0u8; 72];
+    msg[0..32].copy_from_slice(nonce);
+    msg[32..40].copy_from_slice(&timestamp.to_le_bytes());
+    msg[40..72].copy_from_slice(&pool_authority.to_bytes());
+    msg
+}
+
+/// Random id a client presents to pick up its nonce range after a reconnect.
+pub fn new_resume_id() -> String {
+    let id_bytes: [u8; 32] = rand::thread_rng().gen();
+    BASE64_URL_SAFE_NO_PAD.encode(id_bytes)
+}